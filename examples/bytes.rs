@@ -6,7 +6,7 @@ fn main() {
     let mut request = Packet::new();
     request.header.message_id = 23839;
     request.header.code = MessageClass::Request(RequestType::Get);
-    request.set_token(vec![0, 0, 57, 116]);
+    request.try_set_token(vec![0, 0, 57, 116]).unwrap();
     request.add_option(CoapOption::UriHost, b"localhost".to_vec());
     request.add_option(CoapOption::UriPath, b"tv1".to_vec());
     assert_eq!(
@@ -3,6 +3,10 @@
 //! Supports both Block1 and Block2 and is intended to be compliant with the
 //! standard but lenient to tolerate mixed use cases.  In-memory caching of
 //! request and response bodies is used to achieve the generic interaction.
+//!
+//! Clients can also use [`BlockHandler`] to drive Block2 downloads and have
+//! their responses reassembled into single packets, as well as to split
+//! requests with large bodies into Block1 uploads.
 
 use alloc::string::String;
 use alloc::vec::Vec;
@@ -15,11 +19,12 @@ use core::time::Duration;
 
 use lru_time_cache::LruCache;
 
-mod block_value;
-
-use crate::error::HandlingError;
-use crate::{CoapOption, CoapRequest, MessageClass, Packet, ResponseType};
-pub use block_value::BlockValue;
+use crate::error::{HandlingError, InvalidBlockValue};
+pub use crate::option_value::{BlockKind, BlockValue};
+use crate::option_value::{OptionValueU32, MAX_SIZE_EXPONENT};
+use crate::{
+    CoapOption, CoapRequest, CoapResponse, MessageClass, Packet, ResponseType,
+};
 
 /// The maximum amount adding a block1 & block2 option to the message could add
 /// to the total size.
@@ -43,12 +48,28 @@ const DEFAULT_MAX_TOTAL_MESSAGE_SIZE: usize = 1152;
 /// responses.
 pub struct BlockHandler<Endpoint: Ord + Clone> {
     config: BlockHandlerConfig,
+    block_config: BlockConfig,
+
+    /// Largest request body accepted from clients, if limited.
+    max_request_body_size: Option<usize>,
 
     /// Maintains a block1 and 2 cache for requests that we expect a client to
     /// soon follow-up and ask about.  If this recency requirement is not
     /// meant, the system will still work however consistency of results will
     /// suffer.
     states: LruCache<RequestCacheKey<Endpoint>, BlockState>,
+
+    /// Client-side Block2 downloads that are being reassembled, keyed by the
+    /// token of their requests so that several can be in progress at once.
+    downloads: LruCache<Vec<u8>, BlockDownload>,
+
+    /// Client-side Block1 uploads that are being sent, keyed like
+    /// `downloads`.
+    uploads: LruCache<Vec<u8>, BlockUpload>,
+
+    /// Reassembled responses of completed Block2 downloads that haven't
+    /// been taken yet, keyed like `downloads`.
+    assembled: LruCache<Vec<u8>, Packet>,
 }
 
 /// The configuration for [`BlockHandler`].
@@ -79,16 +100,325 @@ impl Default for BlockHandlerConfig {
     }
 }
 
+/// The client-side block transfer configuration for [`BlockHandler`].
+#[derive(Debug, Clone)]
+pub struct BlockConfig {
+    /// Size exponent (SZX) of the blocks requested from the server, must be
+    /// in the range 0 (16 bytes) to 6 (1024 bytes).  Lowering it can help on
+    /// very constrained links.
+    pub default_szx: u8,
+
+    /// Whether the first request of a download already asks for a Block2
+    /// response of `default_szx` (early negotiation).  Otherwise the server
+    /// picks the block size of its response, which is only reduced to
+    /// `default_szx` for the follow-up requests.
+    pub prefer_block2_on_response: bool,
+}
+
+impl Default for BlockConfig {
+    fn default() -> Self {
+        Self {
+            default_szx: MAX_SIZE_EXPONENT,
+            prefer_block2_on_response: true,
+        }
+    }
+}
+
+/// The result of handling a response with [`BlockHandler::handle`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockOutcome {
+    /// The transfer is over.  For downloads, carries the reassembled
+    /// response: the last response received with the full payload and
+    /// without its Block2 and Size2 options.  For uploads, carries the final
+    /// response of the server.
+    Complete(Packet),
+    /// More blocks are needed.  Carries the request for the next block.
+    NeedMore(Packet),
+    /// The response isn't part of a block-wise download.
+    NotBlockWise,
+}
+
 impl<Endpoint: Ord + Clone> BlockHandler<Endpoint> {
     /// Creates a new block handler which is expected to be re-used across all
     /// subsequent request/response pairs that may benefit from block handling.
     pub fn new(config: BlockHandlerConfig) -> Self {
-        Self {
+        Self::new_with_config(config, BlockConfig::default())
+            .expect("Default block config must be valid")
+    }
+
+    /// Creates a new block handler like [`BlockHandler::new`], using
+    /// `block_config` for client-side transfers.
+    ///
+    /// Returns an error if `block_config` contains an invalid size exponent.
+    pub fn new_with_config(
+        config: BlockHandlerConfig,
+        block_config: BlockConfig,
+    ) -> Result<Self, InvalidBlockValue> {
+        if block_config.default_szx > MAX_SIZE_EXPONENT {
+            return Err(InvalidBlockValue::SizeExponentOutOfRange(
+                block_config.default_szx,
+            ));
+        }
+
+        Ok(Self {
             states: LruCache::with_expiry_duration(
                 config.cache_expiry_duration,
             ),
+            downloads: LruCache::with_expiry_duration(
+                config.cache_expiry_duration,
+            ),
+            uploads: LruCache::with_expiry_duration(
+                config.cache_expiry_duration,
+            ),
+            assembled: LruCache::with_expiry_duration(
+                config.cache_expiry_duration,
+            ),
             config,
+            block_config,
+            max_request_body_size: None,
+        })
+    }
+
+    /// Limits the size of request bodies accepted from clients.  Requests
+    /// announcing a larger body in their Size1 option, or carrying blocks
+    /// beyond it, are rejected with 4.13 Request Entity Too Large and a
+    /// Size1 option indicating the limit (RFC 7959, Section 4).
+    pub fn set_max_request_body_size(&mut self, max_size: Option<usize>) {
+        self.max_request_body_size = max_size;
+    }
+
+    /// Starts a client-side Block2 download of the resource targeted by
+    /// `request`, replacing any transfer in progress under the same token.
+    ///
+    /// Returns the first request to send to the server.  Responses to it (and
+    /// to the follow-up requests produced along the way) must be passed to
+    /// [`BlockHandler::handle`].
+    pub fn start_download(&mut self, request: Packet) -> Packet {
+        let mut first_request = request;
+        if self.block_config.prefer_block2_on_response {
+            first_request.set_options_as::<BlockValue>(
+                CoapOption::Block2,
+                [BlockValue {
+                    num: 0,
+                    more: false,
+                    size_exponent: self.block_config.default_szx,
+                }]
+                .into(),
+            );
+        }
+
+        self.uploads.remove(first_request.get_token());
+        self.assembled.remove(first_request.get_token());
+        self.downloads.insert(
+            first_request.get_token().to_vec(),
+            BlockDownload {
+                last_request: first_request.clone(),
+                payload: Vec::new(),
+            },
+        );
+
+        first_request
+    }
+
+    /// Starts a client-side Block1 upload of the payload of `request`,
+    /// replacing any transfer in progress under the same token.
+    ///
+    /// Returns the first request to send to the server, which carries the
+    /// first block along with a Size1 option announcing the total size of
+    /// the payload.  Responses to it (and to the follow-up requests produced
+    /// along the way) must be passed to [`BlockHandler::handle`].  Payloads
+    /// that fit into a single block are sent as they are.
+    pub fn start_upload(&mut self, request: Packet) -> Packet {
+        let block1 = BlockValue {
+            num: 0,
+            more: true,
+            size_exponent: self.block_config.default_szx,
+        };
+        if request.payload.len() <= block1.size() {
+            self.downloads.remove(request.get_token());
+            self.uploads.remove(request.get_token());
+            self.assembled.remove(request.get_token());
+            return request;
+        }
+
+        let mut first_request = request;
+        let payload = mem::take(&mut first_request.payload);
+        first_request.payload = payload[..block1.size()].to_vec();
+        first_request
+            .set_options_as::<BlockValue>(CoapOption::Block1, [block1].into());
+        if let Ok(size1) = u32::try_from(payload.len()) {
+            first_request.set_options_as::<OptionValueU32>(
+                CoapOption::Size1,
+                [OptionValueU32(size1)].into(),
+            );
+        }
+
+        self.downloads.remove(first_request.get_token());
+        self.assembled.remove(first_request.get_token());
+        self.uploads.insert(
+            first_request.get_token().to_vec(),
+            BlockUpload {
+                last_request: first_request.clone(),
+                payload,
+            },
+        );
+
+        first_request
+    }
+
+    /// Handles a response received by the client for a transfer started
+    /// with [`BlockHandler::start_download`] or
+    /// [`BlockHandler::start_upload`], identified by its token.
+    ///
+    /// Returns [`BlockOutcome::NeedMore`] with the request for the next block
+    /// while the transfer is in progress and [`BlockOutcome::Complete`] once
+    /// it is over.  For downloads, this carries the reassembled response once
+    /// the last block has been received.  Responses that don't belong to any
+    /// download, or that carry no Block2 option, yield
+    /// [`BlockOutcome::NotBlockWise`]; the latter also ends the download
+    /// since the server sent the entire representation at once.  For uploads,
+    /// any response other than 2.31 Continue is the final one and completes
+    /// the transfer.
+    ///
+    /// The reassembled response of a download is also kept for
+    /// [`BlockHandler::take_assembled`].
+    pub fn handle(&mut self, response: &Packet) -> BlockOutcome {
+        if self.uploads.peek(response.get_token()).is_some() {
+            return self.handle_upload_response(response);
+        }
+
+        let token = response.get_token();
+        let maybe_block2 = response
+            .get_first_option_as::<BlockValue>(CoapOption::Block2)
+            .and_then(|x| x.ok());
+        let (download, block2) =
+            match (self.downloads.get_mut(token), maybe_block2) {
+                (Some(download), Some(block2)) => (download, block2),
+                (Some(_), None) => {
+                    self.downloads.remove(token);
+                    return BlockOutcome::NotBlockWise;
+                }
+                (None, _) => return BlockOutcome::NotBlockWise,
+            };
+
+        // Blocks are requested one after the other, so anything beyond what
+        // we have so far can't be a response to our requests.
+        let offset = usize::try_from(block2.num).unwrap() * block2.size();
+        if offset > download.payload.len() {
+            return BlockOutcome::NotBlockWise;
+        }
+        download.payload.truncate(offset);
+        download.payload.extend(&response.payload);
+
+        if !block2.more {
+            let mut packet = response.clone();
+            packet.payload = mem::take(&mut download.payload);
+            packet.options.remove(&u16::from(CoapOption::Block2));
+            packet.options.remove(&u16::from(CoapOption::Size2));
+            self.downloads.remove(token);
+            self.assembled.insert(token.to_vec(), packet.clone());
+            return BlockOutcome::Complete(packet);
+        }
+
+        // The client may ask for smaller blocks than the server offered, in
+        // which case the number needs to be adjusted to the new size.
+        let size_exponent =
+            min(block2.size_exponent, self.block_config.default_szx);
+        let next_offset = offset + block2.size();
+        let mut next_block2 = BlockValue {
+            num: 0,
+            more: false,
+            size_exponent,
+        };
+        next_block2.num = match u32::try_from(next_offset / next_block2.size())
+        {
+            Ok(num) => num,
+            Err(_) => return BlockOutcome::NotBlockWise,
+        };
+
+        let mut next_request = download.last_request.clone();
+        next_request.header.message_id =
+            next_request.header.message_id.wrapping_add(1);
+        next_request.set_options_as::<BlockValue>(
+            CoapOption::Block2,
+            [next_block2].into(),
+        );
+        download.last_request.clone_from(&next_request);
+
+        BlockOutcome::NeedMore(next_request)
+    }
+
+    /// Returns the reassembled response of the completed download with the
+    /// given token, carrying the full payload and the options of the
+    /// response minus Block2 and Size2.
+    ///
+    /// Returns `None` if no such download has completed since it was started
+    /// or the response was last taken.  Responses that aren't taken expire
+    /// like the transfers in progress.
+    pub fn take_assembled(&mut self, token: &[u8]) -> Option<Packet> {
+        self.assembled.remove(token)
+    }
+
+    fn handle_upload_response(&mut self, response: &Packet) -> BlockOutcome {
+        let token = response.get_token();
+        if response.header.code
+            != MessageClass::Response(ResponseType::Continue)
+        {
+            self.uploads.remove(token);
+            return BlockOutcome::Complete(response.clone());
+        }
+
+        let upload = match self.uploads.get_mut(token) {
+            Some(upload) => upload,
+            None => return BlockOutcome::NotBlockWise,
+        };
+        let last_block1 = match upload
+            .last_request
+            .get_first_option_as::<BlockValue>(CoapOption::Block1)
+            .and_then(|x| x.ok())
+        {
+            Some(last_block1) => last_block1,
+            None => return BlockOutcome::NotBlockWise,
+        };
+
+        // The server may ask for smaller blocks in its response, in which
+        // case the number needs to be adjusted to the new size.
+        let size_exponent = response
+            .get_first_option_as::<BlockValue>(CoapOption::Block1)
+            .and_then(|x| x.ok())
+            .map_or(last_block1.size_exponent, |block1| {
+                min(block1.size_exponent, last_block1.size_exponent)
+            });
+        let offset = last_block1.offset() + last_block1.size();
+        if offset >= upload.payload.len() {
+            self.uploads.remove(token);
+            return BlockOutcome::NotBlockWise;
         }
+        let mut next_block1 = BlockValue {
+            num: 0,
+            more: false,
+            size_exponent,
+        };
+        next_block1.num = match u32::try_from(offset / next_block1.size()) {
+            Ok(num) => num,
+            Err(_) => return BlockOutcome::NotBlockWise,
+        };
+        let end = min(offset + next_block1.size(), upload.payload.len());
+        next_block1.more = end < upload.payload.len();
+
+        let mut next_request = upload.last_request.clone();
+        next_request.header.message_id =
+            next_request.header.message_id.wrapping_add(1);
+        // Only the first block announces the total size.
+        next_request.options.remove(&u16::from(CoapOption::Size1));
+        next_request.set_options_as::<BlockValue>(
+            CoapOption::Block1,
+            [next_block1].into(),
+        );
+        next_request.payload = upload.payload[offset..end].to_vec();
+        upload.last_request.clone_from(&next_request);
+
+        BlockOutcome::NeedMore(next_request)
     }
 
     /// Intercepts request before application processing has occurred.
@@ -108,6 +438,7 @@ impl<Endpoint: Ord + Clone> BlockHandler<Endpoint> {
         let block1_handled = Self::maybe_handle_request_block1(
             request,
             self.config.max_total_message_size,
+            self.max_request_body_size,
             state,
         )?;
         if block1_handled {
@@ -126,12 +457,30 @@ impl<Endpoint: Ord + Clone> BlockHandler<Endpoint> {
     fn maybe_handle_request_block1(
         request: &mut CoapRequest<Endpoint>,
         max_total_message_size: usize,
+        max_request_body_size: Option<usize>,
         state: &mut BlockState,
     ) -> Result<bool, HandlingError> {
         let request_block1 = request
             .message
             .get_first_option_as::<BlockValue>(CoapOption::Block1)
             .and_then(|x| x.ok());
+
+        if let Some(max_size) = max_request_body_size {
+            if Self::exceeds_request_body_size(
+                &request.message,
+                request_block1.as_ref(),
+                max_size,
+            ) {
+                state.cached_request_payload = None;
+                let response = CoapResponse::entity_too_large(
+                    &request.message,
+                    u32::try_from(max_size).unwrap_or(u32::MAX),
+                )
+                .ok_or_else(HandlingError::not_handled)?;
+                request.response = Some(response);
+                return Ok(true);
+            }
+        }
         let maybe_response_block1 = Self::negotiate_block_size_if_necessary(
             request_block1.as_ref(),
             Self::compute_message_size_hack(&mut request.message),
@@ -205,6 +554,23 @@ impl<Endpoint: Ord + Clone> BlockHandler<Endpoint> {
         }
     }
 
+    /// Returns true if the body of the request, as announced by its Size1
+    /// option or as received so far, is larger than `max_size`.
+    fn exceeds_request_body_size(
+        request: &Packet,
+        request_block1: Option<&BlockValue>,
+        max_size: usize,
+    ) -> bool {
+        let announced_size = request
+            .get_first_option_as::<OptionValueU32>(CoapOption::Size1)
+            .and_then(|x| x.ok())
+            .map_or(0, |size1| usize::try_from(size1.0).unwrap_or(usize::MAX));
+        let received_size = request_block1
+            .map_or(0, BlockValue::offset)
+            .saturating_add(request.payload.len());
+        announced_size > max_size || received_size > max_size
+    }
+
     fn maybe_handle_request_block2(
         request: &mut CoapRequest<Endpoint>,
         state: &mut BlockState,
@@ -413,7 +779,7 @@ pub fn extending_splice<R, I, T>(
     range: R,
     replace_with: I,
     maximum_reserve_len: usize,
-) -> Result<alloc::vec::Splice<I::IntoIter>, String>
+) -> Result<alloc::vec::Splice<'_, I::IntoIter>, String>
 where
     R: RangeBounds<usize>,
     I: IntoIterator<Item = T>,
@@ -433,7 +799,7 @@ where
             ));
         }
         // Safe but inefficient way...
-        dst.extend(iter::repeat(T::default()).take(extend_len));
+        dst.extend(iter::repeat_n(T::default(), extend_len));
     }
 
     Ok(dst.splice(range, replace_with))
@@ -484,12 +850,35 @@ pub struct BlockState {
     cached_request_payload: Option<Vec<u8>>,
 }
 
+/// State of a client-side Block1 upload.
+#[derive(Debug, Clone)]
+struct BlockUpload {
+    /// Last request sent to the server, used as the template for sending the
+    /// next block.
+    last_request: Packet,
+
+    /// Full payload being uploaded.
+    payload: Vec<u8>,
+}
+
+/// State of a client-side Block2 download.
+#[derive(Debug, Clone)]
+struct BlockDownload {
+    /// Last request sent to the server, used as the template for requesting
+    /// the next block.
+    last_request: Packet,
+
+    /// Payload reassembled so far.
+    payload: Vec<u8>,
+}
+
 #[cfg(test)]
+#[allow(deprecated)]
 mod tests {
     use alloc::{borrow::ToOwned, collections::LinkedList};
 
-    use crate::option_value::OptionValueString;
-    use crate::{CoapResponse, RequestType, ResponseType};
+    use crate::option_value::{OptionValueString, OptionValueU32};
+    use crate::{ContentFormat, RequestType, ResponseType};
 
     use super::*;
 
@@ -684,6 +1073,441 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_client_download_assembled() {
+        let resource = "0123456789".repeat(250).into_bytes();
+
+        let mut handler =
+            BlockHandler::<TestEndpoint>::new(BlockHandlerConfig::default());
+
+        let mut request = Packet::new();
+        request.header.code = MessageClass::Request(RequestType::Get);
+        request.header.message_id = 1;
+        request.set_token(vec![0x7d, 0x34]);
+        request.add_option(CoapOption::UriPath, b"test".to_vec());
+
+        assert!(handler.take_assembled(&[0x7d, 0x34]).is_none());
+
+        let first_request = handler.start_download(request);
+        let first_response = serve_block2(&first_request, &resource);
+        let second_request = match handler.handle(&first_response) {
+            BlockOutcome::NeedMore(next_request) => next_request,
+            outcome => panic!("Unexpected outcome: {:?}", outcome),
+        };
+        assert!(handler.take_assembled(&[0x7d, 0x34]).is_none());
+        let second_response = serve_block2(&second_request, &resource);
+        let third_request = match handler.handle(&second_response) {
+            BlockOutcome::NeedMore(next_request) => next_request,
+            outcome => panic!("Unexpected outcome: {:?}", outcome),
+        };
+        assert!(handler.take_assembled(&[0x7d, 0x34]).is_none());
+        let third_response = serve_block2(&third_request, &resource);
+        let completed = match handler.handle(&third_response) {
+            BlockOutcome::Complete(completed) => completed,
+            outcome => panic!("Unexpected outcome: {:?}", outcome),
+        };
+
+        let assembled = handler.take_assembled(&[0x7d, 0x34]).unwrap();
+        assert_eq!(assembled, completed);
+        assert!(handler.take_assembled(&[0x7d, 0x34]).is_none());
+        assert_eq!(assembled.payload, resource);
+        assert_eq!(assembled.get_token(), &[0x7d, 0x34]);
+        assert_eq!(
+            assembled.header.code,
+            MessageClass::Response(ResponseType::Content)
+        );
+        assert_eq!(
+            assembled.get_content_format(),
+            Some(ContentFormat::TextPlain)
+        );
+        assert!(assembled.get_option(CoapOption::Block2).is_none());
+        assert!(assembled.get_option(CoapOption::Size2).is_none());
+
+        // The download is over, so repeated responses are ignored.
+        assert_eq!(
+            handler.handle(&third_response),
+            BlockOutcome::NotBlockWise
+        );
+    }
+
+    #[test]
+    fn test_client_download_not_block_wise() {
+        let mut handler =
+            BlockHandler::<TestEndpoint>::new(BlockHandlerConfig::default());
+
+        let mut request = Packet::new();
+        request.header.code = MessageClass::Request(RequestType::Get);
+        request.set_token(vec![0x01]);
+        let first_request = handler.start_download(request);
+
+        let mut response = CoapResponse::new(&first_request).unwrap();
+        response.message.payload = b"small".to_vec();
+        assert_eq!(
+            handler.handle(&response.message),
+            BlockOutcome::NotBlockWise
+        );
+
+        let next_response = serve_block2(&first_request, b"small");
+        assert_eq!(handler.handle(&next_response), BlockOutcome::NotBlockWise);
+    }
+
+    #[test]
+    fn test_client_interleaved_downloads() {
+        let resources = [
+            "0123456789".repeat(250).into_bytes(),
+            "abcdefghij".repeat(300).into_bytes(),
+        ];
+        let tokens = [vec![0x01], vec![0x02, 0x02]];
+
+        let mut handler =
+            BlockHandler::<TestEndpoint>::new(BlockHandlerConfig::default());
+
+        let mut sent_requests: Vec<_> = tokens
+            .iter()
+            .map(|token| {
+                let mut request = Packet::new();
+                request.header.code = MessageClass::Request(RequestType::Get);
+                request.set_token(token.clone());
+                Some(handler.start_download(request))
+            })
+            .collect();
+        let mut assembled = vec![None, None];
+
+        while sent_requests.iter().any(Option::is_some) {
+            for (i, maybe_sent_request) in sent_requests.iter_mut().enumerate()
+            {
+                if let Some(sent_request) = maybe_sent_request.take() {
+                    let received_response =
+                        serve_block2(&sent_request, &resources[i]);
+                    match handler.handle(&received_response) {
+                        BlockOutcome::NeedMore(next_request) => {
+                            *maybe_sent_request = Some(next_request)
+                        }
+                        BlockOutcome::Complete(packet) => {
+                            assembled[i] = Some(packet)
+                        }
+                        BlockOutcome::NotBlockWise => {
+                            panic!("Download unexpectedly ended")
+                        }
+                    }
+                }
+            }
+        }
+
+        for (i, packet) in assembled.into_iter().enumerate() {
+            let packet = packet.unwrap();
+            assert_eq!(packet.get_token(), tokens[i].as_slice());
+            assert_eq!(packet.payload, resources[i]);
+        }
+    }
+
+    #[test]
+    fn test_client_downloads_completed_back_to_back() {
+        let resources = [
+            "0123456789".repeat(150).into_bytes(),
+            "abcdefghij".repeat(200).into_bytes(),
+        ];
+        let tokens = [vec![0x01], vec![0x02, 0x02]];
+
+        let mut handler =
+            BlockHandler::<TestEndpoint>::new(BlockHandlerConfig::default());
+
+        let last_responses: Vec<_> = tokens
+            .iter()
+            .zip(&resources)
+            .map(|(token, resource)| {
+                let mut request = Packet::new();
+                request.header.code = MessageClass::Request(RequestType::Get);
+                request.set_token(token.clone());
+                let first_request = handler.start_download(request);
+                let first_response = serve_block2(&first_request, resource);
+                match handler.handle(&first_response) {
+                    BlockOutcome::NeedMore(next_request) => {
+                        serve_block2(&next_request, resource)
+                    }
+                    outcome => panic!("Unexpected outcome: {:?}", outcome),
+                }
+            })
+            .collect();
+
+        for last_response in &last_responses {
+            assert!(matches!(
+                handler.handle(last_response),
+                BlockOutcome::Complete(_)
+            ));
+        }
+
+        for (token, resource) in tokens.iter().zip(&resources) {
+            let assembled = handler.take_assembled(token).unwrap();
+            assert_eq!(assembled.get_token(), token.as_slice());
+            assert_eq!(&assembled.payload, resource);
+            assert!(handler.take_assembled(token).is_none());
+        }
+    }
+
+    #[test]
+    fn test_client_download_block_config() {
+        let resource = "0123456789abcdef".repeat(12).into_bytes();
+
+        let mut request = Packet::new();
+        request.header.code = MessageClass::Request(RequestType::Get);
+        request.add_option(CoapOption::UriPath, b"test".to_vec());
+
+        assert_eq!(
+            BlockHandler::<TestEndpoint>::new_with_config(
+                BlockHandlerConfig::default(),
+                BlockConfig {
+                    default_szx: 7,
+                    prefer_block2_on_response: true,
+                },
+            )
+            .err(),
+            Some(InvalidBlockValue::SizeExponentOutOfRange(7))
+        );
+
+        let mut handler = BlockHandler::<TestEndpoint>::new_with_config(
+            BlockHandlerConfig::default(),
+            BlockConfig {
+                default_szx: 2,
+                prefer_block2_on_response: true,
+            },
+        )
+        .unwrap();
+        let first_request = handler.start_download(request.clone());
+        let block2 = first_request
+            .get_first_option_as::<BlockValue>(CoapOption::Block2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(block2.num, 0);
+        assert_eq!(block2.size(), 64);
+
+        // Without early negotiation, the server's block size is reduced in
+        // the follow-up requests.
+        let mut handler = BlockHandler::<TestEndpoint>::new_with_config(
+            BlockHandlerConfig::default(),
+            BlockConfig {
+                default_szx: 2,
+                prefer_block2_on_response: false,
+            },
+        )
+        .unwrap();
+        let first_request = handler.start_download(request);
+        assert!(first_request.get_option(CoapOption::Block2).is_none());
+
+        let mut first_response = CoapResponse::new(&first_request).unwrap();
+        first_response.message.add_option_as(
+            CoapOption::Block2,
+            BlockValue::new(0, true, 128).unwrap(),
+        );
+        first_response.message.payload = resource[..128].to_vec();
+        let next_request = match handler.handle(&first_response.message) {
+            BlockOutcome::NeedMore(next_request) => next_request,
+            outcome => panic!("Unexpected outcome: {:?}", outcome),
+        };
+        let next_block2 = next_request
+            .get_first_option_as::<BlockValue>(CoapOption::Block2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(next_block2.num, 2);
+        assert_eq!(next_block2.size(), 64);
+
+        let last_response = serve_block2(&next_request, &resource);
+        match handler.handle(&last_response) {
+            BlockOutcome::Complete(assembled) => {
+                assert_eq!(assembled.payload, resource)
+            }
+            outcome => panic!("Unexpected outcome: {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_client_upload_size1() {
+        let body = "0123456789".repeat(15).into_bytes();
+
+        let mut client = BlockHandler::<TestEndpoint>::new_with_config(
+            BlockHandlerConfig::default(),
+            BlockConfig {
+                default_szx: 2,
+                prefer_block2_on_response: true,
+            },
+        )
+        .unwrap();
+        let mut harness = TestServerHarness::new(128);
+
+        let mut request = create_put_request("test", 1, &body, None).message;
+        request.set_token(vec![0x42]);
+
+        let mut sent_request = client.start_upload(request);
+        assert_eq!(
+            sent_request
+                .get_first_option_as::<OptionValueU32>(CoapOption::Size1),
+            Some(Ok(OptionValueU32(150)))
+        );
+
+        let mut sent_blocks = 1;
+        let final_response = loop {
+            let mut received_request = CoapRequest::from_packet(
+                sent_request.clone(),
+                TestEndpoint::TestClient,
+            );
+            let block1 = sent_request
+                .get_first_option_as::<BlockValue>(CoapOption::Block1)
+                .unwrap()
+                .unwrap();
+            let received_response = if block1.more {
+                harness.exchange_messages_using_cache(&mut received_request)
+            } else {
+                harness.exchange_messages(
+                    &mut received_request,
+                    |received_request| {
+                        assert_eq!(received_request.message.payload, body);
+                        let sent_response =
+                            received_request.response.as_mut().unwrap();
+                        sent_response.message.header.code =
+                            MessageClass::Response(ResponseType::Changed);
+                        InterceptPolicy::NotExpected
+                    },
+                )
+            }
+            .unwrap();
+
+            match client.handle(&received_response.message) {
+                BlockOutcome::NeedMore(next_request) => {
+                    assert!(next_request
+                        .get_option(CoapOption::Size1)
+                        .is_none());
+                    sent_request = next_request;
+                    sent_blocks += 1;
+                }
+                BlockOutcome::Complete(packet) => break packet,
+                BlockOutcome::NotBlockWise => panic!("Upload ended"),
+            }
+        };
+
+        assert_eq!(sent_blocks, 3);
+        assert_eq!(
+            final_response.header.code,
+            MessageClass::Response(ResponseType::Changed)
+        );
+        assert_eq!(final_response.get_token(), &[0x42]);
+        assert!(client.take_assembled(&[0x42]).is_none());
+    }
+
+    #[test]
+    fn test_client_upload_reused_token() {
+        let body = "0123456789".repeat(15).into_bytes();
+
+        let mut client = BlockHandler::<TestEndpoint>::new_with_config(
+            BlockHandlerConfig::default(),
+            BlockConfig {
+                default_szx: 3,
+                prefer_block2_on_response: true,
+            },
+        )
+        .unwrap();
+        let mut request = create_put_request("test", 1, &body, None).message;
+        request.set_token(vec![0x42]);
+        let continue_response = |sent_request: &Packet| {
+            let mut response = CoapResponse::new(sent_request).unwrap();
+            response.set_status(ResponseType::Continue);
+            response.message
+        };
+
+        // A body fitting into a single block replaces the upload in progress.
+        let first_request = client.start_upload(request.clone());
+        let mut small_request = request.clone();
+        small_request.payload = b"small".to_vec();
+        assert_eq!(client.start_upload(small_request.clone()), small_request);
+        assert_eq!(
+            client.handle(&continue_response(&first_request)),
+            BlockOutcome::NotBlockWise
+        );
+
+        // Acknowledging the last block with 2.31 Continue ends the upload.
+        let first_request = client.start_upload(request);
+        let last_request =
+            match client.handle(&continue_response(&first_request)) {
+                BlockOutcome::NeedMore(next_request) => next_request,
+                outcome => panic!("Unexpected outcome: {:?}", outcome),
+            };
+        assert_eq!(
+            client.handle(&continue_response(&last_request)),
+            BlockOutcome::NotBlockWise
+        );
+        let mut final_response = CoapResponse::new(&last_request).unwrap();
+        final_response.set_status(ResponseType::Changed);
+        assert_eq!(
+            client.handle(&final_response.message),
+            BlockOutcome::NotBlockWise
+        );
+    }
+
+    #[test]
+    fn test_server_rejects_large_size1() {
+        let mut harness = TestServerHarness::new(1152);
+        harness.handler.set_max_request_body_size(Some(100));
+
+        let block1 = BlockValue::new(0, true, 64).unwrap();
+        let mut sent_request =
+            create_put_request("test", 1, &[0; 64], Some(block1));
+        sent_request
+            .message
+            .add_option_as(CoapOption::Size1, OptionValueU32(150));
+        let received_response = harness
+            .exchange_messages_using_cache(&mut sent_request)
+            .unwrap();
+
+        assert_eq!(
+            received_response.message.header.code,
+            MessageClass::Response(ResponseType::RequestEntityTooLarge)
+        );
+        assert_eq!(
+            received_response
+                .message
+                .get_first_option_as::<OptionValueU32>(CoapOption::Size1),
+            Some(Ok(OptionValueU32(100)))
+        );
+
+        // Within the limit, the upload proceeds as usual.
+        sent_request
+            .message
+            .set_options_as(CoapOption::Size1, [OptionValueU32(90)].into());
+        sent_request.response = CoapResponse::new(&sent_request.message);
+        let received_response = harness
+            .exchange_messages_using_cache(&mut sent_request)
+            .unwrap();
+        assert_eq!(
+            received_response.message.header.code,
+            MessageClass::Response(ResponseType::Continue)
+        );
+    }
+
+    /// Produces the server's response to a Block2 request for `resource`.
+    fn serve_block2(request: &Packet, resource: &[u8]) -> Packet {
+        let block2 = request
+            .get_first_option_as::<BlockValue>(CoapOption::Block2)
+            .unwrap()
+            .unwrap();
+        let start = usize::try_from(block2.num).unwrap() * block2.size();
+        let end = min(start + block2.size(), resource.len());
+
+        let mut response = CoapResponse::new(request).unwrap().message;
+        response.set_content_format(ContentFormat::TextPlain);
+        response.add_option_as(
+            CoapOption::Size2,
+            OptionValueU32(u32::try_from(resource.len()).unwrap()),
+        );
+        response.add_option_as(
+            CoapOption::Block2,
+            BlockValue {
+                more: end < resource.len(),
+                ..block2
+            },
+        );
+        response.payload = resource[start..end].to_vec();
+        response
+    }
+
     struct TestServerHarness {
         handler: BlockHandler<TestEndpoint>,
     }
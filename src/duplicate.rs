@@ -0,0 +1,86 @@
+use alloc::collections::VecDeque;
+
+const DEFAULT_CAPACITY: usize = 64;
+
+/// Detects retransmitted messages by remembering the source address and
+/// message ID of the most recently received ones (RFC 7252, Section 4.5).
+///
+/// Entries are evicted oldest first once `capacity` is reached rather than
+/// after `EXCHANGE_LIFETIME`, so the capacity should cover the number of
+/// messages expected within that time.
+pub struct DuplicateDetector<A: PartialEq> {
+    seen: VecDeque<(A, u16)>,
+    capacity: usize,
+}
+
+impl<A: PartialEq> DuplicateDetector<A> {
+    /// Creates a detector remembering up to `capacity` messages.
+    pub fn new(capacity: usize) -> DuplicateDetector<A> {
+        DuplicateDetector {
+            seen: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Returns true if a message with the same source address and message ID
+    /// has been seen before, and otherwise records it.
+    pub fn is_duplicate(&mut self, addr: A, message_id: u16) -> bool {
+        if self.seen.iter().any(|(seen_addr, seen_id)| {
+            *seen_id == message_id && *seen_addr == addr
+        }) {
+            return true;
+        }
+
+        if self.capacity == 0 {
+            return false;
+        }
+        if self.seen.len() == self.capacity {
+            self.seen.pop_front();
+        }
+        self.seen.push_back((addr, message_id));
+        false
+    }
+
+    /// Returns the number of messages remembered.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Returns true if no messages are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+impl<A: PartialEq> Default for DuplicateDetector<A> {
+    fn default() -> Self {
+        DuplicateDetector::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_duplicates() {
+        let mut detector = DuplicateDetector::new(8);
+        assert!(!detector.is_duplicate("10.0.0.1", 42));
+        assert!(detector.is_duplicate("10.0.0.1", 42));
+        assert!(!detector.is_duplicate("10.0.0.2", 42));
+        assert!(!detector.is_duplicate("10.0.0.1", 43));
+        assert_eq!(detector.len(), 3);
+    }
+
+    #[test]
+    fn evicts_oldest_entries() {
+        let mut detector = DuplicateDetector::new(2);
+        assert!(!detector.is_duplicate(1, 1));
+        assert!(!detector.is_duplicate(1, 2));
+        assert!(!detector.is_duplicate(1, 3));
+        assert_eq!(detector.len(), 2);
+
+        assert!(detector.is_duplicate(1, 3));
+        assert!(!detector.is_duplicate(1, 1));
+    }
+}
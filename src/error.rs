@@ -8,16 +8,25 @@ use core::{fmt, num::TryFromIntError};
 #[cfg(feature = "std")]
 use std::error;
 
-use crate::ResponseType;
+use crate::{RequestType, ResponseType};
 
 /// The errors that can occur when encoding/decoding packets.
 #[derive(Debug, PartialEq)]
 pub enum MessageError {
+    /// No longer returned by this crate and only kept for compatibility;
+    /// messages shorter than the header fail with
+    /// [`MessageError::UnexpectedEnd`].
     InvalidHeader,
     InvalidPacketLength,
     InvalidTokenLength,
     InvalidOptionDelta,
     InvalidOptionLength,
+    TooManyOptions,
+    ConflictingOptions,
+    RepeatedOption,
+    InvalidHex,
+    Malformed,
+    UnexpectedEnd,
 }
 
 impl fmt::Display for MessageError {
@@ -38,6 +47,24 @@ impl fmt::Display for MessageError {
             MessageError::InvalidOptionLength => {
                 write!(f, "CoAP error: invalid option length")
             }
+            MessageError::TooManyOptions => {
+                write!(f, "CoAP error: too many options for the buffer")
+            }
+            MessageError::ConflictingOptions => {
+                write!(f, "CoAP error: conflicting options")
+            }
+            MessageError::RepeatedOption => {
+                write!(f, "CoAP error: non-repeatable option repeated")
+            }
+            MessageError::InvalidHex => {
+                write!(f, "CoAP error: invalid hex encoding")
+            }
+            MessageError::Malformed => {
+                write!(f, "CoAP error: payload marker without payload")
+            }
+            MessageError::UnexpectedEnd => {
+                write!(f, "CoAP error: message shorter than the header")
+            }
         }
     }
 }
@@ -92,6 +119,8 @@ pub enum InvalidBlockValue {
     SizeExponentEncodingError(usize),
     TypeBoundsError(TryFromIntError),
     MaximumNumberExceeded(u32),
+    SizeExponentOutOfRange(u8),
+    UnalignedOffset(usize),
 }
 
 impl fmt::Display for InvalidBlockValue {
@@ -106,6 +135,12 @@ impl fmt::Display for InvalidBlockValue {
             InvalidBlockValue::MaximumNumberExceeded(size) => {
                 write!(f, "block number {} cannot be encoded", size)
             }
+            InvalidBlockValue::SizeExponentOutOfRange(size_exponent) => {
+                write!(f, "size exponent {} is out of range", size_exponent)
+            }
+            InvalidBlockValue::UnalignedOffset(offset) => {
+                write!(f, "offset {} is not aligned to the block size", offset)
+            }
         }
     }
 }
@@ -113,6 +148,87 @@ impl fmt::Display for InvalidBlockValue {
 #[cfg(feature = "std")]
 impl error::Error for InvalidBlockValue {}
 
+/// The errors that can occur when parsing a CoAP URI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvalidUri {
+    /// The scheme is neither `coap` nor `coaps`.
+    UnsupportedScheme,
+    /// The host is missing or malformed.
+    InvalidHost,
+    /// The port is not a valid number.
+    InvalidPort,
+    /// A percent-encoded sequence is malformed.
+    InvalidPercentEncoding,
+    /// The URI has a fragment, which CoAP doesn't support.
+    Fragment,
+}
+
+impl fmt::Display for InvalidUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidUri::UnsupportedScheme => {
+                write!(f, "CoAP error: unsupported URI scheme")
+            }
+            InvalidUri::InvalidHost => {
+                write!(f, "CoAP error: invalid URI host")
+            }
+            InvalidUri::InvalidPort => {
+                write!(f, "CoAP error: invalid URI port")
+            }
+            InvalidUri::InvalidPercentEncoding => {
+                write!(f, "CoAP error: invalid percent-encoding in URI")
+            }
+            InvalidUri::Fragment => {
+                write!(f, "CoAP error: URI must not have a fragment")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for InvalidUri {}
+
+/// The error returned when a response code is unusual for the method of the
+/// request it answers, e.g. 2.05 Content for a DELETE.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResponseCodeMismatch {
+    pub method: RequestType,
+    pub status: ResponseType,
+}
+
+impl fmt::Display for ResponseCodeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CoAP error: unexpected response {:?} to a {:?} request",
+            self.status, self.method
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for ResponseCodeMismatch {}
+
+/// The error returned when setting the Observe option on a request whose
+/// method doesn't support observation, which is limited to GET and FETCH.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObserveNotAllowed {
+    pub method: RequestType,
+}
+
+impl fmt::Display for ObserveNotAllowed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CoAP error: observe is not allowed for a {:?} request",
+            self.method
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for ObserveNotAllowed {}
+
 /// Participatory mechanism for the low-level library to communicate to callers
 /// that unexpected errors occurred while handling standard parts of the
 /// protocol that should ideally deliver a failure message to the peer. But
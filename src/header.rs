@@ -15,6 +15,12 @@ pub struct HeaderRaw {
 }
 
 impl HeaderRaw {
+    /// Returns the 4 bytes of the header as they appear in a message.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let id_bytes = self.message_id.to_be_bytes();
+        [self.ver_type_tkl, self.code, id_bytes[0], id_bytes[1]]
+    }
+
     /// Writes the header into the given buffer, which must have a capacity of
     /// at least 4.
     pub fn serialize_into(
@@ -48,8 +54,9 @@ impl TryFrom<&[u8]> for HeaderRaw {
     type Error = MessageError;
 
     fn try_from(buf: &[u8]) -> Result<HeaderRaw, MessageError> {
+        // Stream readers rely on this to tell that more bytes are needed.
         if buf.len() < 4 {
-            return Err(MessageError::InvalidPacketLength);
+            return Err(MessageError::UnexpectedEnd);
         }
 
         let mut id_bytes = [0; 2];
@@ -204,6 +211,28 @@ pub enum RequestType {
     UnKnown,
 }
 
+impl RequestType {
+    /// Returns true if the method is safe, i.e. only retrieves a
+    /// representation without modifying the resource (GET and FETCH).
+    pub fn is_safe(&self) -> bool {
+        matches!(self, RequestType::Get | RequestType::Fetch)
+    }
+
+    /// Returns true if the method is idempotent, i.e. repeating the request
+    /// has the same effect as sending it once, which makes it suitable for
+    /// automatic retries (GET, PUT, DELETE, FETCH and iPATCH).
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            RequestType::Get
+                | RequestType::Put
+                | RequestType::Delete
+                | RequestType::Fetch
+                | RequestType::IPatch
+        )
+    }
+}
+
 /// The response codes.
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum ResponseType {
@@ -251,7 +280,7 @@ impl ResponseType {
 }
 
 /// The message types.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MessageType {
     Confirmable,
     NonConfirmable,
@@ -259,6 +288,41 @@ pub enum MessageType {
     Reset,
 }
 
+impl MessageType {
+    /// Returns the message type for the 2-bit type field, or `None` if
+    /// `value` doesn't fit into it.
+    pub fn from_u8(value: u8) -> Option<MessageType> {
+        match value {
+            0 => Some(MessageType::Confirmable),
+            1 => Some(MessageType::NonConfirmable),
+            2 => Some(MessageType::Acknowledgement),
+            3 => Some(MessageType::Reset),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the 2-bit type field.
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            MessageType::Confirmable => 0,
+            MessageType::NonConfirmable => 1,
+            MessageType::Acknowledgement => 2,
+            MessageType::Reset => 3,
+        }
+    }
+}
+
+/// The fields of a message header as plain values, e.g. for logging or
+/// metrics, see [`Header::fields`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderFields {
+    pub version: u8,
+    pub type_: MessageType,
+    pub code_class: u8,
+    pub code_detail: u8,
+    pub message_id: u16,
+}
+
 /// The message header.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Header {
@@ -313,28 +377,16 @@ impl Header {
     /// Sets the message type.
     #[inline]
     pub fn set_type(&mut self, t: MessageType) {
-        let tn = match t {
-            MessageType::Confirmable => 0,
-            MessageType::NonConfirmable => 1,
-            MessageType::Acknowledgement => 2,
-            MessageType::Reset => 3,
-        };
-
         let ver_tkl = 0xCF & self.ver_type_tkl;
-        self.ver_type_tkl = tn << 4 | ver_tkl;
+        self.ver_type_tkl = t.as_u8() << 4 | ver_tkl;
     }
 
     /// Returns the message type.
     #[inline]
     pub fn get_type(&self) -> MessageType {
         let tn = (0x30 & self.ver_type_tkl) >> 4;
-        match tn {
-            0 => MessageType::Confirmable,
-            1 => MessageType::NonConfirmable,
-            2 => MessageType::Acknowledgement,
-            3 => MessageType::Reset,
-            _ => unreachable!(),
-        }
+        // The two bits can only hold valid types
+        MessageType::from_u8(tn).unwrap()
     }
 
     /// Sets the token length.
@@ -369,12 +421,70 @@ impl Header {
     pub fn get_code(&self) -> String {
         self.code.to_string()
     }
+
+    /// Returns the raw message code byte.
+    #[inline]
+    pub fn code_raw(&self) -> u8 {
+        self.code.into()
+    }
+
+    /// Sets the message code from its raw byte, which is useful for codes
+    /// that don't have a dedicated variant; those are kept as
+    /// [`MessageClass::Reserved`].
+    #[inline]
+    pub fn set_code_raw(&mut self, code: u8) {
+        self.code = code.into();
+    }
+
+    /// Returns the header fields in one struct.
+    pub fn fields(&self) -> HeaderFields {
+        let code = self.code_raw();
+        HeaderFields {
+            version: self.get_version(),
+            type_: self.get_type(),
+            code_class: code >> 5,
+            code_detail: code & 0x1F,
+            message_id: self.message_id,
+        }
+    }
+
+    /// Sets the message code to the given request method.
+    #[inline]
+    pub fn set_code_from_request(&mut self, request_type: RequestType) {
+        self.code = MessageClass::Request(request_type);
+    }
+
+    /// Sets the message code to the given response code.
+    #[inline]
+    pub fn set_code_from_response(&mut self, response_type: ResponseType) {
+        self.code = MessageClass::Response(response_type);
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_header_fields() {
+        let mut header = Header::new();
+        header.set_type(MessageType::NonConfirmable);
+        header.set_code_from_response(ResponseType::NotFound);
+        header.message_id = 0x1234;
+
+        let HeaderFields {
+            version,
+            type_,
+            code_class,
+            code_detail,
+            message_id,
+        } = header.fields();
+        assert_eq!(version, 1);
+        assert_eq!(type_, MessageType::NonConfirmable);
+        assert_eq!((code_class, code_detail), (4, 4));
+        assert_eq!(message_id, 0x1234);
+    }
+
     #[test]
     fn test_header_codes() {
         for code in 0..255 {
@@ -394,6 +504,30 @@ mod test {
         }
     }
 
+    #[test]
+    fn set_code_from_types() {
+        let mut header = Header::new();
+        header.set_code_from_response(ResponseType::Content);
+        assert_eq!("2.05", header.get_code());
+        header.set_code_from_request(RequestType::Get);
+        assert_eq!("0.01", header.get_code());
+        assert_eq!(0x01, header.to_raw().code);
+    }
+
+    #[test]
+    fn raw_codes() {
+        let mut header = Header::new();
+        header.set_code_raw(0x05);
+        assert_eq!(0x05, header.code_raw());
+        assert_eq!("0.05", header.get_code());
+        assert_eq!(MessageClass::Request(RequestType::Fetch), header.code);
+
+        header.set_code_raw(0x1f);
+        assert_eq!(MessageClass::Reserved(0x1f), header.code);
+        assert_eq!(0x1f, header.code_raw());
+        assert_eq!(0x1f, header.to_raw().code);
+    }
+
     #[test]
     fn serialize_raw_fail() {
         let h = HeaderRaw::default();
@@ -408,7 +542,7 @@ mod test {
     fn from_bytes_fail() {
         let b: &[u8] = &[1, 2, 3];
         assert_eq!(
-            MessageError::InvalidPacketLength,
+            MessageError::UnexpectedEnd,
             HeaderRaw::try_from(b).unwrap_err()
         );
     }
@@ -426,6 +560,33 @@ mod test {
         assert_eq!(MessageType::Reset, h.get_type());
     }
 
+    #[test]
+    fn message_type_values() {
+        for t in [
+            MessageType::Confirmable,
+            MessageType::NonConfirmable,
+            MessageType::Acknowledgement,
+            MessageType::Reset,
+        ] {
+            assert_eq!(Some(t), MessageType::from_u8(t.as_u8()));
+        }
+        assert_eq!(None, MessageType::from_u8(4));
+    }
+
+    #[test]
+    fn safe_and_idempotent_methods() {
+        assert!(RequestType::Get.is_safe());
+        assert!(RequestType::Get.is_idempotent());
+        assert!(!RequestType::Put.is_safe());
+        assert!(RequestType::Put.is_idempotent());
+        assert!(!RequestType::Post.is_safe());
+        assert!(!RequestType::Post.is_idempotent());
+        assert!(RequestType::Fetch.is_safe());
+        assert!(!RequestType::Patch.is_idempotent());
+        assert!(RequestType::IPatch.is_idempotent());
+        assert!(!RequestType::UnKnown.is_idempotent());
+    }
+
     #[test]
     fn is_error() {
         assert!(!ResponseType::Created.is_error());
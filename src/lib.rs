@@ -92,7 +92,7 @@
 //! let mut request = Packet::new();
 //! request.header.message_id = 23839;
 //! request.header.code = MessageClass::Request(RequestType::Get);
-//! request.set_token(vec![0, 0, 57, 116]);
+//! request.try_set_token(vec![0, 0, 57, 116]).unwrap();
 //! request.add_option(CoapOption::UriHost, b"localhost".to_vec());
 //! request.add_option(CoapOption::UriPath, b"tv1".to_vec());
 //! assert_eq!(
@@ -131,6 +131,7 @@ pub mod error;
 
 #[cfg(feature = "std")]
 pub mod block_handler;
+mod duplicate;
 mod header;
 pub mod link_format;
 #[macro_use]
@@ -145,11 +146,20 @@ mod impl_coap_message;
 mod impl_coap_message_0_3;
 
 #[cfg(feature = "std")]
-pub use block_handler::{BlockHandler, BlockHandlerConfig};
+pub use block_handler::{
+    BlockConfig, BlockHandler, BlockHandlerConfig, BlockOutcome,
+};
+pub use duplicate::DuplicateDetector;
 pub use header::{
-    Header, HeaderRaw, MessageClass, MessageType, RequestType, ResponseType,
+    Header, HeaderFields, HeaderRaw, MessageClass, MessageType, RequestType,
+    ResponseType,
 };
 pub use observe::{create_notification, Subject};
-pub use packet::{CoapOption, ContentFormat, ObserveOption, Packet};
+pub use option_value::BlockKind;
+pub use packet::{
+    CoapOption, ContentFormat, IfMatch, LazyPacket, NoResponseClass,
+    ObserveOption, OptionRef, OptionWriter, OscoreClass, Packet, ParsedView,
+    SliceWriter,
+};
 pub use request::CoapRequest;
 pub use response::CoapResponse;
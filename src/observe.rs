@@ -191,6 +191,7 @@ pub fn create_notification(
     });
     packet.header.code = MessageClass::Response(crate::ResponseType::Content);
     packet.header.message_id = message_id;
+    #[allow(deprecated)]
     packet.set_token(token);
     packet.payload = payload;
     packet.set_observe_value(sequence);
@@ -209,6 +210,7 @@ impl<Endpoint: Display + PartialEq + Clone> Default for Subject<Endpoint> {
 }
 
 #[cfg(test)]
+#[allow(deprecated)]
 mod test {
     use super::*;
     use crate::{
@@ -6,7 +6,7 @@ use alloc::{
 };
 use core::convert::TryFrom;
 
-use crate::error::IncompatibleOptionValueFormat;
+use crate::error::{IncompatibleOptionValueFormat, InvalidBlockValue};
 
 /// Supertrait for types that can be used as option values.
 pub trait OptionValueType:
@@ -109,3 +109,248 @@ impl TryFrom<Vec<u8>> for OptionValueString {
 }
 
 impl OptionValueType for OptionValueString {}
+
+/// The block option value.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BlockValue {
+    pub num: u32,
+    pub more: bool,
+    pub size_exponent: u8,
+}
+
+// 2^20 - 1
+const MAX_BLOCK_NUMBER: u32 = 1048575;
+
+/// Largest size exponent allowed by RFC 7959 (1024 bytes), 7 is reserved.
+pub(crate) const MAX_SIZE_EXPONENT: u8 = 6;
+
+impl BlockValue {
+    pub fn new(
+        num: usize,
+        more: bool,
+        size: usize,
+    ) -> Result<Self, InvalidBlockValue> {
+        let true_size_exponent = Self::largest_power_of_2_not_in_excess(size)
+            .ok_or(InvalidBlockValue::SizeExponentEncodingError(size))?;
+
+        let size_exponent = u8::try_from(true_size_exponent.saturating_sub(4))
+            .map_err(InvalidBlockValue::TypeBoundsError)?;
+        if size_exponent > 0x7 {
+            return Err(InvalidBlockValue::SizeExponentEncodingError(size));
+        }
+        let num =
+            u32::try_from(num).map_err(InvalidBlockValue::TypeBoundsError)?;
+        if num > MAX_BLOCK_NUMBER {
+            return Err(InvalidBlockValue::MaximumNumberExceeded(num));
+        }
+        Ok(Self {
+            num,
+            more,
+            size_exponent,
+        })
+    }
+
+    /// Creates the block value for the block starting at byte `offset` of a
+    /// body split into blocks of `size` bytes.
+    ///
+    /// Returns an error if `size` isn't a valid block size, including sizes
+    /// above 1024 bytes whose size exponent is reserved, or if `offset` isn't
+    /// a multiple of it.
+    pub fn for_offset(
+        offset: usize,
+        size: usize,
+        more: bool,
+    ) -> Result<Self, InvalidBlockValue> {
+        if !size.is_power_of_two() {
+            return Err(InvalidBlockValue::SizeExponentEncodingError(size));
+        }
+        if size > 1 << (MAX_SIZE_EXPONENT + 4) {
+            let size_exponent = u8::try_from(size.trailing_zeros() - 4)
+                .map_err(InvalidBlockValue::TypeBoundsError)?;
+            return Err(InvalidBlockValue::SizeExponentOutOfRange(
+                size_exponent,
+            ));
+        }
+        if offset & (size - 1) != 0 {
+            return Err(InvalidBlockValue::UnalignedOffset(offset));
+        }
+
+        let block = Self::new(offset / size, more, size)?;
+        // Sizes below the minimum block size would otherwise be rounded up.
+        if block.size() != size {
+            return Err(InvalidBlockValue::SizeExponentEncodingError(size));
+        }
+        Ok(block)
+    }
+
+    /// Finds the largest power of 2 that does not exceed `target`.
+    fn largest_power_of_2_not_in_excess(target: usize) -> Option<usize> {
+        if target == 0 {
+            return None;
+        }
+
+        let max_power = usize::try_from(usize::BITS).unwrap();
+        let power_in_excess = (0..max_power).find(|i| (1 << i) > target);
+
+        match power_in_excess {
+            Some(size) => Some(size - 1),
+            None => Some(max_power),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        1 << (self.size_exponent + 4)
+    }
+
+    /// Returns the zero-based index of the block (its number) and, if the
+    /// total size of the body is known (e.g. from a Size1 or Size2 option),
+    /// the total number of blocks of this size, for progress reporting.
+    pub fn progress(
+        &self,
+        total_size: Option<usize>,
+    ) -> (usize, Option<usize>) {
+        let index = usize::try_from(self.num).unwrap();
+        let total_blocks =
+            total_size.map(|total_size| total_size.div_ceil(self.size()));
+        (index, total_blocks)
+    }
+
+    /// Returns the byte offset of the block into the body.
+    pub fn offset(&self) -> usize {
+        usize::try_from(self.num).unwrap() * self.size()
+    }
+}
+
+impl From<BlockValue> for Vec<u8> {
+    fn from(block_value: BlockValue) -> Vec<u8> {
+        let scalar = block_value.num << 4
+            | u32::from(block_value.more) << 3
+            | u32::from(block_value.size_exponent & 0x7);
+        Vec::from(OptionValueU32(scalar))
+    }
+}
+
+impl TryFrom<Vec<u8>> for BlockValue {
+    type Error = IncompatibleOptionValueFormat;
+
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let scalar = OptionValueU32::try_from(value)?.0;
+
+        let num: u32 = scalar >> 4;
+        let more = scalar >> 3 & 0x1 == 0x1;
+        let size_exponent: u8 = (scalar & 0x7) as u8;
+        Ok(Self {
+            num,
+            more,
+            size_exponent,
+        })
+    }
+}
+
+impl OptionValueType for BlockValue {}
+
+/// The block options carried by a packet, see [`Packet::any_block`](crate::Packet::any_block).
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockKind {
+    Block1(BlockValue),
+    Block2(BlockValue),
+    /// Both options are present, e.g. in the last request of a Block1
+    /// upload that asks for the first block of the response.
+    Both {
+        block1: BlockValue,
+        block2: BlockValue,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highest_containing_power_of_2() {
+        assert_eq!(BlockValue::largest_power_of_2_not_in_excess(0), None);
+        assert_eq!(BlockValue::largest_power_of_2_not_in_excess(256), Some(8));
+        assert_eq!(BlockValue::largest_power_of_2_not_in_excess(257), Some(8));
+        assert_eq!(
+            BlockValue::largest_power_of_2_not_in_excess(usize::MAX),
+            Some(usize::try_from(usize::BITS).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_block_value_exponent() {
+        assert!(BlockValue::new(0, false, 0).is_err());
+        assert!(BlockValue::new(0, false, usize::MAX).is_err());
+        assert_eq!(
+            BlockValue::new(0, false, 1158).unwrap(),
+            BlockValue {
+                num: 0,
+                more: false,
+                size_exponent: 6
+            }
+        );
+        assert_eq!(
+            BlockValue::new(0, false, 256).unwrap(),
+            BlockValue {
+                num: 0,
+                more: false,
+                size_exponent: 4
+            }
+        );
+    }
+
+    #[test]
+    fn test_block_value_for_offset() {
+        let block = BlockValue::for_offset(2048, 512, true).unwrap();
+        assert_eq!(
+            block,
+            BlockValue {
+                num: 4,
+                more: true,
+                size_exponent: 5
+            }
+        );
+        assert_eq!(block.offset(), 2048);
+
+        assert_eq!(
+            BlockValue::for_offset(2000, 512, true),
+            Err(InvalidBlockValue::UnalignedOffset(2000))
+        );
+        assert_eq!(
+            BlockValue::for_offset(0, 500, false),
+            Err(InvalidBlockValue::SizeExponentEncodingError(500))
+        );
+        assert_eq!(
+            BlockValue::for_offset(64, 8, false),
+            Err(InvalidBlockValue::SizeExponentEncodingError(8))
+        );
+        assert_eq!(
+            BlockValue::for_offset(0, 2048, false),
+            Err(InvalidBlockValue::SizeExponentOutOfRange(7))
+        );
+        assert!(BlockValue::for_offset(0, 1024, false).is_ok());
+    }
+
+    #[test]
+    fn test_block_value_progress() {
+        let block = BlockValue::new(2, true, 1024).unwrap();
+        assert_eq!(block.progress(Some(7000)), (2, Some(7)));
+        assert_eq!(block.progress(Some(7168)), (2, Some(7)));
+        assert_eq!(block.progress(Some(7169)), (2, Some(8)));
+        assert_eq!(block.progress(None), (2, None));
+    }
+
+    #[test]
+    fn encode_block_opt_4096() {
+        let opt = BlockValue::new(4096, false, 1024).unwrap();
+        let bytes = Vec::<u8>::from(opt);
+        assert_eq!(bytes, vec![0x01, 0x00, 0x06]);
+    }
+
+    #[test]
+    fn encode_block_opt_4095() {
+        let opt = BlockValue::new(4095, false, 1024).unwrap();
+        let bytes = Vec::<u8>::from(opt);
+        assert_eq!(bytes, vec![0xff, 0xf6]);
+    }
+}
@@ -1,8 +1,14 @@
 use alloc::{
-    collections::{BTreeMap, LinkedList},
+    collections::{BTreeMap, BTreeSet, LinkedList},
+    string::{String, ToString},
     vec::Vec,
 };
-use core::convert::TryFrom;
+use core::{
+    convert::TryFrom,
+    fmt::{self, Write},
+    ops::{BitOr, RangeInclusive},
+    str::FromStr,
+};
 
 use crate::{
     error::{
@@ -10,17 +16,12 @@ use crate::{
         MessageError,
     },
     header::{Header, HeaderRaw, MessageClass},
-    option_value::{OptionValueType, OptionValueU16, OptionValueU32},
+    option_value::{
+        BlockKind, BlockValue, OptionValueType, OptionValueU16,
+        OptionValueU32, OptionValueU64, OptionValueU8,
+    },
 };
 
-macro_rules! u8_to_unsigned_be {
-    ($src:ident, $start:expr, $end:expr, $t:ty) => ({
-        (0..=$end - $start).rev().fold(
-            0, |acc, i| acc | $src[$start+i] as $t << i * 8
-        )
-    })
-}
-
 /// The CoAP options.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CoapOption {
@@ -106,6 +107,127 @@ impl From<CoapOption> for u16 {
     }
 }
 
+/// The protection classes of options in OSCORE (RFC 8613, Section 4.1).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OscoreClass {
+    /// Class E, encrypted and integrity protected in the inner message.
+    Encrypted,
+    /// Class I, integrity protected only.  No such options are defined yet.
+    IntegrityProtected,
+    /// Class U, unprotected in the outer message.
+    Unprotected,
+}
+
+impl CoapOption {
+    /// Returns the option number of the option.
+    #[inline]
+    pub fn number(&self) -> u16 {
+        u16::from(*self)
+    }
+
+    /// Returns the option for the given option number, which is
+    /// [`CoapOption::Unknown`] for numbers without a dedicated variant.
+    #[inline]
+    pub fn from_number(number: u16) -> CoapOption {
+        CoapOption::from(number)
+    }
+
+    /// Returns true if the option is not part of the cache key of a request.
+    ///
+    /// This covers the options marked NoCacheKey by their number (RFC 7252,
+    /// Section 5.4.6) such as Size1 and Size2, as well as ETag, which a
+    /// client uses to validate stored responses (RFC 7252, Section 5.6.2),
+    /// and Observe, which doesn't change the representation being requested
+    /// (RFC 7641, Section 2).
+    pub fn is_no_cache_key(number: u16) -> bool {
+        number & 0x1E == 0x1C
+            || matches!(
+                CoapOption::from(number),
+                CoapOption::ETag | CoapOption::Observe
+            )
+    }
+
+    /// Returns the range of valid value lengths of the option (RFC 7252,
+    /// Section 5.10 and the RFCs defining later options), or `None` if it
+    /// isn't known.
+    fn value_length_range(&self) -> Option<RangeInclusive<usize>> {
+        match self {
+            CoapOption::IfNoneMatch => Some(0..=0),
+            CoapOption::NoResponse => Some(0..=1),
+            CoapOption::UriPort
+            | CoapOption::ContentFormat
+            | CoapOption::Accept => Some(0..=2),
+            CoapOption::Observe | CoapOption::Block2 | CoapOption::Block1 => {
+                Some(0..=3)
+            }
+            CoapOption::MaxAge | CoapOption::Size1 | CoapOption::Size2 => {
+                Some(0..=4)
+            }
+            CoapOption::IfMatch => Some(0..=8),
+            CoapOption::ETag => Some(1..=8),
+            CoapOption::LocationPath
+            | CoapOption::Oscore
+            | CoapOption::UriPath
+            | CoapOption::UriQuery
+            | CoapOption::LocationQuery => Some(0..=255),
+            CoapOption::UriHost | CoapOption::ProxyScheme => Some(1..=255),
+            CoapOption::ProxyUri => Some(1..=1034),
+            CoapOption::Unknown(_) => None,
+        }
+    }
+
+    /// Returns true if the option may occur more than once in a message.
+    /// Options that aren't known are assumed to be repeatable.
+    fn is_repeatable(&self) -> bool {
+        matches!(
+            self,
+            CoapOption::IfMatch
+                | CoapOption::ETag
+                | CoapOption::LocationPath
+                | CoapOption::UriPath
+                | CoapOption::UriQuery
+                | CoapOption::LocationQuery
+                | CoapOption::Unknown(_)
+        )
+    }
+
+    /// Returns true if the values of the option are unsigned integers
+    /// (RFC 7252, Section 3.2).
+    fn is_uint(&self) -> bool {
+        matches!(
+            self,
+            CoapOption::Observe
+                | CoapOption::UriPort
+                | CoapOption::ContentFormat
+                | CoapOption::MaxAge
+                | CoapOption::Accept
+                | CoapOption::Block2
+                | CoapOption::Block1
+                | CoapOption::Size1
+                | CoapOption::Size2
+                | CoapOption::NoResponse
+        )
+    }
+
+    /// Returns the OSCORE class of the option with the given number (RFC
+    /// 8613, Table 4).
+    ///
+    /// Options that are used both as inner and outer options (Observe,
+    /// Max-Age, the Block and Size options and No-Response) are Class E, as
+    /// their end-to-end value belongs into the inner message.  Options
+    /// without a defined class are Class E as well, per the default rule.
+    pub fn oscore_class(number: u16) -> OscoreClass {
+        match CoapOption::from(number) {
+            CoapOption::UriHost
+            | CoapOption::UriPort
+            | CoapOption::Oscore
+            | CoapOption::ProxyUri
+            | CoapOption::ProxyScheme => OscoreClass::Unprotected,
+            _ => OscoreClass::Encrypted,
+        }
+    }
+}
+
 /// The content formats.
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[non_exhaustive]
@@ -319,6 +441,297 @@ impl From<ContentFormat> for usize {
     }
 }
 
+/// Every content format, for looking them up by media type.
+const CONTENT_FORMATS: [ContentFormat; 60] = [
+    ContentFormat::TextPlain,
+    ContentFormat::ApplicationCoseEncrypt0,
+    ContentFormat::ApplicationCoseMac0,
+    ContentFormat::ApplicationCoseSign1,
+    ContentFormat::ApplicationAceCbor,
+    ContentFormat::ImageGif,
+    ContentFormat::ImageJpeg,
+    ContentFormat::ImagePng,
+    ContentFormat::ApplicationLinkFormat,
+    ContentFormat::ApplicationXML,
+    ContentFormat::ApplicationOctetStream,
+    ContentFormat::ApplicationEXI,
+    ContentFormat::ApplicationJSON,
+    ContentFormat::ApplicationJsonPatchJson,
+    ContentFormat::ApplicationMergePatchJson,
+    ContentFormat::ApplicationCBOR,
+    ContentFormat::ApplicationCWt,
+    ContentFormat::ApplicationMultipartCore,
+    ContentFormat::ApplicationCborSeq,
+    ContentFormat::ApplicationCoseEncrypt,
+    ContentFormat::ApplicationCoseMac,
+    ContentFormat::ApplicationCoseSign,
+    ContentFormat::ApplicationCoseKey,
+    ContentFormat::ApplicationCoseKeySet,
+    ContentFormat::ApplicationSenmlJSON,
+    ContentFormat::ApplicationSensmlJSON,
+    ContentFormat::ApplicationSenmlCBOR,
+    ContentFormat::ApplicationSensmlCBOR,
+    ContentFormat::ApplicationSenmlExi,
+    ContentFormat::ApplicationSensmlExi,
+    ContentFormat::ApplicationYangDataCborSid,
+    ContentFormat::ApplicationCoapGroupJson,
+    ContentFormat::ApplicationDotsCbor,
+    ContentFormat::ApplicationMissingBlocksCborSeq,
+    ContentFormat::ApplicationPkcs7MimeServerGeneratedKey,
+    ContentFormat::ApplicationPkcs7MimeCertsOnly,
+    ContentFormat::ApplicationPkcs8,
+    ContentFormat::ApplicationCsrattrs,
+    ContentFormat::ApplicationPkcs10,
+    ContentFormat::ApplicationPkixCert,
+    ContentFormat::ApplicationAifCbor,
+    ContentFormat::ApplicationAifJson,
+    ContentFormat::ApplicationSenmlXML,
+    ContentFormat::ApplicationSensmlXML,
+    ContentFormat::ApplicationSenmlEtchJson,
+    ContentFormat::ApplicationSenmlEtchCbor,
+    ContentFormat::ApplicationYangDataCbor,
+    ContentFormat::ApplicationYangDataCborName,
+    ContentFormat::ApplicationTdJson,
+    ContentFormat::ApplicationVoucherCoseCbor,
+    ContentFormat::ApplicationVndOcfCbor,
+    ContentFormat::ApplicationOscore,
+    ContentFormat::ApplicationJavascript,
+    ContentFormat::ApplicationJsonDeflate,
+    ContentFormat::ApplicationCborDeflate,
+    ContentFormat::ApplicationVndOmaLwm2mTlv,
+    ContentFormat::ApplicationVndOmaLwm2mJson,
+    ContentFormat::ApplicationVndOmaLwm2mCbor,
+    ContentFormat::TextCss,
+    ContentFormat::ImageSvgXml,
+];
+
+impl ContentFormat {
+    /// Returns the media type as registered in the IANA "CoAP
+    /// Content-Formats" registry, without the content coding, see
+    /// [`ContentFormat::content_coding`].
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            ContentFormat::TextPlain => "text/plain; charset=utf-8",
+            ContentFormat::ApplicationCoseEncrypt0 => {
+                "application/cose; cose-type=\"cose-encrypt0\""
+            }
+            ContentFormat::ApplicationCoseMac0 => {
+                "application/cose; cose-type=\"cose-mac0\""
+            }
+            ContentFormat::ApplicationCoseSign1 => {
+                "application/cose; cose-type=\"cose-sign1\""
+            }
+            ContentFormat::ApplicationAceCbor => "application/ace+cbor",
+            ContentFormat::ImageGif => "image/gif",
+            ContentFormat::ImageJpeg => "image/jpeg",
+            ContentFormat::ImagePng => "image/png",
+            ContentFormat::ApplicationLinkFormat => "application/link-format",
+            ContentFormat::ApplicationXML => "application/xml",
+            ContentFormat::ApplicationOctetStream => {
+                "application/octet-stream"
+            }
+            ContentFormat::ApplicationEXI => "application/exi",
+            ContentFormat::ApplicationJSON => "application/json",
+            ContentFormat::ApplicationJsonPatchJson => {
+                "application/json-patch+json"
+            }
+            ContentFormat::ApplicationMergePatchJson => {
+                "application/merge-patch+json"
+            }
+            ContentFormat::ApplicationCBOR => "application/cbor",
+            ContentFormat::ApplicationCWt => "application/cwt",
+            ContentFormat::ApplicationMultipartCore => {
+                "application/multipart-core"
+            }
+            ContentFormat::ApplicationCborSeq => "application/cbor-seq",
+            ContentFormat::ApplicationCoseEncrypt => {
+                "application/cose; cose-type=\"cose-encrypt\""
+            }
+            ContentFormat::ApplicationCoseMac => {
+                "application/cose; cose-type=\"cose-mac\""
+            }
+            ContentFormat::ApplicationCoseSign => {
+                "application/cose; cose-type=\"cose-sign\""
+            }
+            ContentFormat::ApplicationCoseKey => "application/cose-key",
+            ContentFormat::ApplicationCoseKeySet => "application/cose-key-set",
+            ContentFormat::ApplicationSenmlJSON => "application/senml+json",
+            ContentFormat::ApplicationSensmlJSON => "application/sensml+json",
+            ContentFormat::ApplicationSenmlCBOR => "application/senml+cbor",
+            ContentFormat::ApplicationSensmlCBOR => "application/sensml+cbor",
+            ContentFormat::ApplicationSenmlExi => "application/senml-exi",
+            ContentFormat::ApplicationSensmlExi => "application/sensml-exi",
+            ContentFormat::ApplicationYangDataCborSid => {
+                "application/yang-data+cbor; id=sid"
+            }
+            ContentFormat::ApplicationCoapGroupJson => {
+                "application/coap-group+json"
+            }
+            ContentFormat::ApplicationDotsCbor => "application/dots+cbor",
+            ContentFormat::ApplicationMissingBlocksCborSeq => {
+                "application/missing-blocks+cbor-seq"
+            }
+            ContentFormat::ApplicationPkcs7MimeServerGeneratedKey => {
+                "application/pkcs7-mime; smime-type=server-generated-key"
+            }
+            ContentFormat::ApplicationPkcs7MimeCertsOnly => {
+                "application/pkcs7-mime; smime-type=certs-only"
+            }
+            ContentFormat::ApplicationPkcs8 => "application/pkcs8",
+            ContentFormat::ApplicationCsrattrs => "application/csrattrs",
+            ContentFormat::ApplicationPkcs10 => "application/pkcs10",
+            ContentFormat::ApplicationPkixCert => "application/pkix-cert",
+            ContentFormat::ApplicationAifCbor => "application/aif+cbor",
+            ContentFormat::ApplicationAifJson => "application/aif+json",
+            ContentFormat::ApplicationSenmlXML => "application/senml+xml",
+            ContentFormat::ApplicationSensmlXML => "application/sensml+xml",
+            ContentFormat::ApplicationSenmlEtchJson => {
+                "application/senml-etch+json"
+            }
+            ContentFormat::ApplicationSenmlEtchCbor => {
+                "application/senml-etch+cbor"
+            }
+            ContentFormat::ApplicationYangDataCbor => {
+                "application/yang-data+cbor"
+            }
+            ContentFormat::ApplicationYangDataCborName => {
+                "application/yang-data+cbor; id=name"
+            }
+            ContentFormat::ApplicationTdJson => "application/td+json",
+            ContentFormat::ApplicationVoucherCoseCbor => {
+                "application/voucher-cose+cbor"
+            }
+            ContentFormat::ApplicationVndOcfCbor => "application/vnd.ocf+cbor",
+            ContentFormat::ApplicationOscore => "application/oscore",
+            ContentFormat::ApplicationJavascript => "application/javascript",
+            ContentFormat::ApplicationJsonDeflate => "application/json",
+            ContentFormat::ApplicationCborDeflate => "application/cbor",
+            ContentFormat::ApplicationVndOmaLwm2mTlv => {
+                "application/vnd.oma.lwm2m+tlv"
+            }
+            ContentFormat::ApplicationVndOmaLwm2mJson => {
+                "application/vnd.oma.lwm2m+json"
+            }
+            ContentFormat::ApplicationVndOmaLwm2mCbor => {
+                "application/vnd.oma.lwm2m+cbor"
+            }
+            ContentFormat::TextCss => "text/css",
+            ContentFormat::ImageSvgXml => "image/svg+xml",
+        }
+    }
+
+    /// Returns the content coding of the format, e.g. `deflate` for
+    /// [`ContentFormat::ApplicationJsonDeflate`], or `None` for the identity
+    /// coding.
+    pub fn content_coding(&self) -> Option<&'static str> {
+        match self {
+            ContentFormat::ApplicationJsonDeflate
+            | ContentFormat::ApplicationCborDeflate => Some("deflate"),
+            _ => None,
+        }
+    }
+
+    /// Returns the media type without parameters, e.g. `text/plain` for
+    /// `text/plain; charset=utf-8`.
+    pub fn base_type(&self) -> &'static str {
+        let media_type = self.media_type();
+        match media_type.find(';') {
+            Some(end) => media_type[..end].trim_end(),
+            None => media_type,
+        }
+    }
+
+    /// Returns true if both content formats have the same base media type
+    /// and content coding, ignoring parameters such as the charset or the
+    /// COSE type.
+    pub fn compatible_with(&self, other: &ContentFormat) -> bool {
+        self.base_type() == other.base_type()
+            && self.content_coding() == other.content_coding()
+    }
+}
+
+/// Formats the media type of the content format, without its content
+/// coding.
+impl fmt::Display for ContentFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.media_type())
+    }
+}
+
+impl FromStr for ContentFormat {
+    type Err = InvalidContentFormat;
+
+    /// Parses a media type, which always yields the format with the
+    /// identity content coding.
+    fn from_str(media_type: &str) -> Result<ContentFormat, Self::Err> {
+        CONTENT_FORMATS
+            .iter()
+            .find(|format| {
+                format.content_coding().is_none()
+                    && format.media_type() == media_type
+            })
+            .copied()
+            .ok_or(InvalidContentFormat)
+    }
+}
+
+/// The precondition expressed by the If-Match option.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IfMatch {
+    /// The empty If-Match: the target resource must exist, whatever its
+    /// current representation.
+    Any,
+    /// The current representation must match one of the entity tags.
+    ETags(Vec<Vec<u8>>),
+}
+
+/// The classes of responses a client isn't interested in, as carried by the
+/// No-Response option (RFC 7967, Section 2.1).
+///
+/// The empty set means that the client is interested in all responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoResponseClass(u8);
+
+impl NoResponseClass {
+    /// Interest in all responses.
+    pub const NONE: NoResponseClass = NoResponseClass(0);
+    /// Suppresses 2.xx success responses.
+    pub const SUCCESS: NoResponseClass = NoResponseClass(2);
+    /// Suppresses 4.xx client error responses.
+    pub const CLIENT_ERROR: NoResponseClass = NoResponseClass(8);
+    /// Suppresses 5.xx server error responses.
+    pub const SERVER_ERROR: NoResponseClass = NoResponseClass(16);
+
+    const ALL_BITS: u8 = 2 | 8 | 16;
+
+    /// Returns the value of the option.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Creates the set from the value of the option, or returns `None` if it
+    /// has bits set that don't correspond to a response class.
+    pub fn from_bits(bits: u8) -> Option<NoResponseClass> {
+        if bits & !Self::ALL_BITS != 0 {
+            return None;
+        }
+        Some(NoResponseClass(bits))
+    }
+
+    /// Returns true if all response classes of `other` are suppressed.
+    pub fn contains(&self, other: NoResponseClass) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for NoResponseClass {
+    type Output = NoResponseClass;
+
+    fn bitor(self, other: NoResponseClass) -> NoResponseClass {
+        NoResponseClass(self.0 | other.0)
+    }
+}
+
 /// The values of the observe option.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ObserveOption {
@@ -356,6 +769,179 @@ pub struct Packet {
     pub payload: Vec<u8>,
 }
 
+/// A reference to an option value inside an encoded packet.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OptionRef<'a> {
+    pub number: u16,
+    pub value: &'a [u8],
+}
+
+/// A packet decoded without allocation by [`Packet::parse_into`], borrowing
+/// from the encoded bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedView<'a> {
+    pub header: Header,
+    pub token: &'a [u8],
+    /// The number of options written to the caller's buffer.
+    pub option_count: usize,
+    pub payload: &'a [u8],
+}
+
+/// A packet whose header, token and payload are decoded eagerly, while its
+/// options are left encoded and only scanned on demand, e.g. for proxies
+/// that look at a few options and forward the rest verbatim.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LazyPacket<'a> {
+    pub header: Header,
+    pub token: &'a [u8],
+    options: &'a [u8],
+    pub payload: &'a [u8],
+}
+
+impl<'a> LazyPacket<'a> {
+    /// Decodes a byte slice, checking that its options are well-formed
+    /// without collecting them.
+    pub fn from_bytes(buf: &'a [u8]) -> Result<LazyPacket<'a>, MessageError> {
+        let (header, token, mut raw_options) = Packet::decode_head(buf)?;
+        let options_start = raw_options.idx;
+        for option in raw_options.by_ref() {
+            option?;
+        }
+
+        Ok(LazyPacket {
+            header,
+            token,
+            options: &buf[options_start..raw_options.idx],
+            payload: raw_options.payload()?,
+        })
+    }
+
+    /// Returns the first value of the option with the given number.
+    pub fn find_option(&self, number: u16) -> Option<&'a [u8]> {
+        self.options_iter()
+            .find(|option| option.number == number)
+            .map(|option| option.value)
+    }
+
+    /// Returns an iterator over the options in the order they are encoded.
+    pub fn options_iter(&self) -> impl Iterator<Item = OptionRef<'a>> {
+        RawOptions {
+            buf: self.options,
+            idx: 0,
+            number: 0,
+        }
+        // The options were checked when decoding the packet.
+        .filter_map(Result::ok)
+    }
+
+    /// Returns the encoded options, excluding the payload marker.
+    pub fn raw_options(&self) -> &'a [u8] {
+        self.options
+    }
+}
+
+/// Iterator decoding the options of an encoded packet, which stops at the
+/// payload marker.
+struct RawOptions<'a> {
+    buf: &'a [u8],
+    idx: usize,
+    number: u16,
+}
+
+impl<'a> RawOptions<'a> {
+    /// Returns the payload following the options, which is only meaningful
+    /// once the iterator has been exhausted.
+    ///
+    /// A payload marker that isn't followed by any payload is a message
+    /// format error (RFC 7252, Section 3).
+    fn payload(&self) -> Result<&'a [u8], MessageError> {
+        if self.idx < self.buf.len() {
+            let payload = &self.buf[(self.idx + 1)..];
+            if payload.is_empty() {
+                return Err(MessageError::Malformed);
+            }
+            Ok(payload)
+        } else {
+            Ok(&[])
+        }
+    }
+
+    /// Decodes the (possibly extended) delta or length for the 4-bit
+    /// `nibble`, failing with `reserved_error` for the reserved value 15.
+    fn decode_extended(
+        &mut self,
+        nibble: u8,
+        reserved_error: MessageError,
+    ) -> Result<usize, MessageError> {
+        let buf = self.buf;
+        match nibble {
+            13 => {
+                let value = *buf
+                    .get(self.idx)
+                    .ok_or(MessageError::InvalidOptionLength)?;
+                self.idx += 1;
+                Ok(usize::from(value) + 13)
+            }
+            14 => {
+                if self.idx + 1 >= buf.len() {
+                    return Err(MessageError::InvalidOptionLength);
+                }
+                let value =
+                    u16::from_be_bytes([buf[self.idx], buf[self.idx + 1]]);
+                self.idx += 2;
+                Ok(usize::from(value) + 269)
+            }
+            15 => Err(reserved_error),
+            _ => Ok(usize::from(nibble)),
+        }
+    }
+
+    fn decode_option(&mut self) -> Result<OptionRef<'a>, MessageError> {
+        let byte = self.buf[self.idx];
+        self.idx += 1;
+
+        let delta =
+            self.decode_extended(byte >> 4, MessageError::InvalidOptionDelta)?;
+        let length = self
+            .decode_extended(byte & 0xF, MessageError::InvalidOptionLength)?;
+
+        self.number = u16::try_from(delta)
+            .ok()
+            .and_then(|delta| self.number.checked_add(delta))
+            .ok_or(MessageError::InvalidOptionDelta)?;
+
+        let end = self.idx + length;
+        if end > self.buf.len() {
+            return Err(MessageError::InvalidOptionLength);
+        }
+        let value = &self.buf[self.idx..end];
+        self.idx = end;
+
+        Ok(OptionRef {
+            number: self.number,
+            value,
+        })
+    }
+}
+
+impl<'a> Iterator for RawOptions<'a> {
+    type Item = Result<OptionRef<'a>, MessageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let byte = *self.buf.get(self.idx)?;
+        if byte == 0xFF {
+            return None;
+        }
+
+        let result = self.decode_option();
+        if result.is_err() {
+            // Don't attempt to decode anything past a malformed option.
+            self.idx = self.buf.len();
+        }
+        Some(result)
+    }
+}
+
 /// An iterator over the options of a packet.
 pub type Options<'a> =
     alloc::collections::btree_map::Iter<'a, u16, LinkedList<Vec<u8>>>;
@@ -376,21 +962,109 @@ impl Packet {
     }
 
     /// Returns an iterator over the options of the packet.
-    pub fn options(&self) -> Options {
+    pub fn options(&self) -> Options<'_> {
         self.options.iter()
     }
 
+    /// Sets the message code.
+    #[inline]
+    pub fn set_code(&mut self, code: MessageClass) {
+        self.header.code = code;
+    }
+
     /// Sets the token.
+    ///
+    /// Tokens longer than 8 bytes are accepted, but serializing the packet
+    /// then fails with [`MessageError::InvalidTokenLength`].
+    #[deprecated(
+        note = "use `try_set_token`, which rejects tokens longer than 8 bytes"
+    )]
     pub fn set_token(&mut self, token: Vec<u8>) {
         self.header.set_token_length(token.len() as u8);
         self.token = token;
     }
 
+    /// Sets the token, failing with [`MessageError::InvalidTokenLength`] if
+    /// it is longer than 8 bytes.
+    pub fn try_set_token(
+        &mut self,
+        token: Vec<u8>,
+    ) -> Result<(), MessageError> {
+        if token.len() > 8 {
+            return Err(MessageError::InvalidTokenLength);
+        }
+        self.header.set_token_length(token.len() as u8);
+        self.token = token;
+        Ok(())
+    }
+
     /// Returns the token.
     pub fn get_token(&self) -> &[u8] {
         &self.token
     }
 
+    /// Returns the token, which is an empty slice for packets without one.
+    #[inline]
+    pub fn token(&self) -> &[u8] {
+        &self.token
+    }
+
+    /// Returns true if both packets carry the same token, including when
+    /// neither has one, e.g. to match a response to its request.
+    #[inline]
+    pub fn token_matches(&self, other: &Packet) -> bool {
+        self.token == other.token
+    }
+
+    /// Returns true if the code is a request method code (0.01 to 0.31),
+    /// including ones this crate doesn't know.  The 0.00 code of empty
+    /// messages isn't a request.
+    pub fn is_request(&self) -> bool {
+        let code = u8::from(self.header.code);
+        code >> 5 == 0 && code != 0
+    }
+
+    /// Returns true if the code is in one of the response classes 2, 4 or 5,
+    /// including codes this crate doesn't know, e.g. 2.00.  The 0.00 code of
+    /// empty messages isn't a response.
+    pub fn is_response(&self) -> bool {
+        matches!(u8::from(self.header.code) >> 5, 2 | 4 | 5)
+    }
+
+    /// Returns the length of the payload.
+    pub fn payload_len(&self) -> usize {
+        self.payload.len()
+    }
+
+    /// Returns true if the packet carries a payload.
+    pub fn has_payload(&self) -> bool {
+        !self.payload.is_empty()
+    }
+
+    /// Moves the payload out of the packet, leaving it empty.
+    pub fn take_payload(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.payload)
+    }
+
+    /// Replaces the payload, returning the previous one.
+    pub fn replace_payload(&mut self, payload: Vec<u8>) -> Vec<u8> {
+        core::mem::replace(&mut self.payload, payload)
+    }
+
+    /// Computes an 8-byte ETag for the payload with the 64-bit FNV-1a hash,
+    /// which is stable across runs and platforms but not collision
+    /// resistant against deliberate attacks.
+    pub fn compute_etag_from_payload(&self) -> Vec<u8> {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let hash =
+            self.payload.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            });
+        hash.to_be_bytes().to_vec()
+    }
+
     /// Sets an option's values.
     pub fn set_option(&mut self, tp: CoapOption, value: LinkedList<Vec<u8>>) {
         self.options.insert(tp.into(), value);
@@ -443,6 +1117,61 @@ impl Packet {
             .map(|value| T::try_from(value.clone()))
     }
 
+    /// Returns an option's first value decoded as an unsigned integer.
+    pub fn get_uint_option(
+        &self,
+        tp: CoapOption,
+    ) -> Result<Option<u64>, IncompatibleOptionValueFormat> {
+        self.get_first_option_as::<OptionValueU64>(tp)
+            .transpose()
+            .map(|option| option.map(|value| value.0))
+    }
+
+    /// Returns an option's first value decoded as a UTF-8 string.
+    pub fn get_string_option(
+        &self,
+        tp: CoapOption,
+    ) -> Result<Option<&str>, IncompatibleOptionValueFormat> {
+        self.get_opaque_option(tp)
+            .map(|value| {
+                core::str::from_utf8(value).map_err(|e| {
+                    IncompatibleOptionValueFormat {
+                        message: e.to_string(),
+                    }
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns an option's first value as opaque bytes.
+    pub fn get_opaque_option(&self, tp: CoapOption) -> Option<&[u8]> {
+        self.get_first_option(tp).map(|value| value.as_slice())
+    }
+
+    /// Copies the options of `template` that the packet doesn't have yet,
+    /// e.g. a default set of Accept or Content-Format options.  Options the
+    /// packet already has keep all of their values.
+    pub fn apply_template(&mut self, template: &Packet) {
+        for (number, values) in template.options.iter() {
+            let present = self
+                .options
+                .get(number)
+                .is_some_and(|values| !values.is_empty());
+            if !present && !values.is_empty() {
+                self.options.insert(*number, values.clone());
+            }
+        }
+    }
+
+    /// Returns the distinct values of an option, for repeatable options
+    /// whose order doesn't matter, such as the ETags of If-Match.
+    pub fn option_set(&self, number: u16) -> BTreeSet<Vec<u8>> {
+        self.options
+            .get(&number)
+            .map(|values| values.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
     /// Adds an option value.
     pub fn add_option(&mut self, tp: CoapOption, value: Vec<u8>) {
         let num = tp.into();
@@ -494,6 +1223,23 @@ impl Packet {
             .and_then(|value| ContentFormat::try_from(value).ok())
     }
 
+    /// Sets the No-Response option.
+    pub fn set_no_response(&mut self, classes: NoResponseClass) {
+        self.clear_option(CoapOption::NoResponse);
+        self.add_option_as(
+            CoapOption::NoResponse,
+            OptionValueU8(classes.bits()),
+        );
+    }
+
+    /// Returns the response classes suppressed by the No-Response option, or
+    /// `None` if it is absent or has an invalid value.
+    pub fn get_no_response(&self) -> Option<NoResponseClass> {
+        self.get_first_option_as::<OptionValueU8>(CoapOption::NoResponse)
+            .and_then(|option| option.ok())
+            .and_then(|value| NoResponseClass::from_bits(value.0))
+    }
+
     /// Sets the value of the observe option.
     pub fn set_observe_value(&mut self, value: u32) {
         self.clear_option(CoapOption::Observe);
@@ -508,130 +1254,156 @@ impl Packet {
             .map(|option| option.map(|value| value.0))
     }
 
+    /// Sets the If-Match option, replacing any previous values. An empty
+    /// list of entity tags removes the option.
+    pub fn set_if_match(&mut self, if_match: IfMatch) {
+        self.options.remove(&CoapOption::IfMatch.into());
+        match if_match {
+            IfMatch::Any => self.add_option(CoapOption::IfMatch, Vec::new()),
+            IfMatch::ETags(etags) => {
+                for etag in etags {
+                    self.add_option(CoapOption::IfMatch, etag);
+                }
+            }
+        }
+    }
+
+    /// Returns the If-Match precondition, if any. An empty value anywhere in
+    /// the option means [`IfMatch::Any`], since it matches every existing
+    /// representation regardless of the other entity tags.
+    pub fn get_if_match(&self) -> Option<IfMatch> {
+        let values = self
+            .get_option(CoapOption::IfMatch)
+            .filter(|values| !values.is_empty())?;
+        if values.iter().any(|value| value.is_empty()) {
+            Some(IfMatch::Any)
+        } else {
+            Some(IfMatch::ETags(values.iter().cloned().collect()))
+        }
+    }
+
     /// Decodes a byte slice and constructs the equivalent packet.
     pub fn from_bytes(buf: &[u8]) -> Result<Packet, MessageError> {
-        let header_result = HeaderRaw::try_from(buf);
-        match header_result {
-            Ok(raw_header) => {
-                let header = Header::from_raw(&raw_header);
-                let token_length = header.get_token_length();
-                let options_start: usize = 4 + token_length as usize;
-
-                if token_length > 8 {
-                    return Err(MessageError::InvalidTokenLength);
-                }
+        let (header, token, mut raw_options) = Self::decode_head(buf)?;
 
-                if options_start > buf.len() {
-                    return Err(MessageError::InvalidTokenLength);
-                }
+        let mut options: BTreeMap<u16, LinkedList<Vec<u8>>> = BTreeMap::new();
+        for option in raw_options.by_ref() {
+            let option = option?;
+            options
+                .entry(option.number)
+                .or_default()
+                .push_back(option.value.to_vec());
+        }
 
-                let token = buf[4..options_start].to_vec();
+        Ok(Packet {
+            header,
+            token: token.to_vec(),
+            options,
+            payload: raw_options.payload()?.to_vec(),
+        })
+    }
 
-                let mut idx = options_start;
-                let mut options_number = 0;
-                let mut options: BTreeMap<u16, LinkedList<Vec<u8>>> =
-                    BTreeMap::new();
-                while idx < buf.len() {
-                    let byte = buf[idx];
+    /// Decodes a byte slice without allocating, writing references to the
+    /// options into `options_out`.
+    ///
+    /// The returned view indicates how many entries of `options_out` were
+    /// written.  Fails with [`MessageError::TooManyOptions`] if the packet has
+    /// more options than fit into `options_out`.
+    pub fn parse_into<'a>(
+        buf: &'a [u8],
+        options_out: &mut [OptionRef<'a>],
+    ) -> Result<ParsedView<'a>, MessageError> {
+        let (header, token, mut raw_options) = Self::decode_head(buf)?;
+
+        let mut option_count = 0;
+        for option in raw_options.by_ref() {
+            let option = option?;
+            let slot = options_out
+                .get_mut(option_count)
+                .ok_or(MessageError::TooManyOptions)?;
+            *slot = option;
+            option_count += 1;
+        }
 
-                    if byte == 255 || idx > buf.len() {
-                        break;
-                    }
+        Ok(ParsedView {
+            header,
+            token,
+            option_count,
+            payload: raw_options.payload()?,
+        })
+    }
 
-                    let mut delta = (byte >> 4) as u16;
-                    let mut length = (byte & 0xF) as usize;
-
-                    idx += 1;
-
-                    // Check for special delta characters
-                    match delta {
-                        13 => {
-                            if idx >= buf.len() {
-                                return Err(MessageError::InvalidOptionLength);
-                            }
-                            delta = (buf[idx] + 13).into();
-                            idx += 1;
-                        }
-                        14 => {
-                            if idx + 1 >= buf.len() {
-                                return Err(MessageError::InvalidOptionLength);
-                            }
-
-                            delta = u16::from_be(u8_to_unsigned_be!(
-                                buf,
-                                idx,
-                                idx + 1,
-                                u16
-                            )) + 269;
-                            idx += 2;
-                        }
-                        15 => {
-                            return Err(MessageError::InvalidOptionDelta);
-                        }
-                        _ => {}
-                    };
-
-                    // Check for special length characters
-                    match length {
-                        13 => {
-                            if idx >= buf.len() {
-                                return Err(MessageError::InvalidOptionLength);
-                            }
-
-                            length = buf[idx] as usize + 13;
-                            idx += 1;
-                        }
-                        14 => {
-                            if idx + 1 >= buf.len() {
-                                return Err(MessageError::InvalidOptionLength);
-                            }
-
-                            length = (u16::from_be(u8_to_unsigned_be!(
-                                buf,
-                                idx,
-                                idx + 1,
-                                u16
-                            )) + 269)
-                                as usize;
-                            idx += 2;
-                        }
-                        15 => {
-                            return Err(MessageError::InvalidOptionLength);
-                        }
-                        _ => {}
-                    };
-
-                    options_number += delta;
-
-                    let end = idx + length;
-                    if end > buf.len() {
-                        return Err(MessageError::InvalidOptionLength);
-                    }
-                    let options_value = buf[idx..end].to_vec();
+    /// Decodes the header and token, returning an iterator over the options
+    /// that follow them.
+    fn decode_head(
+        buf: &[u8],
+    ) -> Result<(Header, &[u8], RawOptions<'_>), MessageError> {
+        let raw_header = HeaderRaw::try_from(buf)?;
+        let header = Header::from_raw(&raw_header);
+        let token_length = header.get_token_length();
+        let options_start: usize = 4 + token_length as usize;
+
+        if token_length > 8 {
+            return Err(MessageError::InvalidTokenLength);
+        }
+
+        if options_start > buf.len() {
+            return Err(MessageError::InvalidTokenLength);
+        }
 
-                    options
-                        .entry(options_number)
-                        .or_default()
-                        .push_back(options_value);
+        let raw_options = RawOptions {
+            buf,
+            idx: options_start,
+            number: 0,
+        };
 
-                    idx += length;
-                }
+        Ok((header, &buf[4..options_start], raw_options))
+    }
 
-                let payload = if idx < buf.len() {
-                    buf[(idx + 1)..buf.len()].to_vec()
-                } else {
-                    Vec::new()
-                };
-
-                Ok(Packet {
-                    header,
-                    token,
-                    options,
-                    payload,
-                })
+    /// Checks that the packet is well-formed and can be sent, i.e. that:
+    ///
+    /// * the token is at most 8 bytes long,
+    /// * the values of known options have valid lengths,
+    /// * non-repeatable options occur at most once,
+    /// * Proxy-Uri isn't combined with any of the Uri-* options, and
+    /// * the packet fits into [`Packet::MAX_SIZE`].
+    ///
+    /// Returns the error of the first check that fails.
+    pub fn validate(&self) -> Result<(), MessageError> {
+        if self.token.len() > 8 {
+            return Err(MessageError::InvalidTokenLength);
+        }
+
+        for (&number, value_list) in self.options.iter() {
+            let option = CoapOption::from(number);
+            if value_list.len() > 1 && !option.is_repeatable() {
+                return Err(MessageError::RepeatedOption);
+            }
+            if let Some(range) = option.value_length_range() {
+                if value_list.iter().any(|value| !range.contains(&value.len()))
+                {
+                    return Err(MessageError::InvalidOptionLength);
+                }
             }
-            Err(_) => Err(MessageError::InvalidHeader),
         }
+
+        let has_option = |option: CoapOption| {
+            self.get_option(option).is_some_and(|list| !list.is_empty())
+        };
+        if has_option(CoapOption::ProxyUri)
+            && [
+                CoapOption::UriHost,
+                CoapOption::UriPort,
+                CoapOption::UriPath,
+                CoapOption::UriQuery,
+            ]
+            .into_iter()
+            .any(has_option)
+        {
+            return Err(MessageError::ConflictingOptions);
+        }
+
+        self.to_bytes().map(|_| ())
     }
 
     /// Returns a vector of bytes representing the Packet.
@@ -654,131 +1426,320 @@ impl Packet {
         self.to_bytes_internal(None)
     }
 
-    fn to_bytes_internal(
-        &self,
-        limit: Option<usize>,
-    ) -> Result<Vec<u8>, MessageError> {
-        let mut options_delta_length = 0;
-        let mut options_bytes: Vec<u8> = Vec::new();
-        for (number, value_list) in self.options.iter() {
-            for value in value_list.iter() {
-                let mut header: Vec<u8> = Vec::with_capacity(1 + 2 + 2);
-                let delta = number - options_delta_length;
-
-                let mut byte: u8 = 0;
-                if delta <= 12 {
-                    byte |= (delta << 4) as u8;
-                } else if delta < 269 {
-                    byte |= 13 << 4;
-                } else {
-                    byte |= 14 << 4;
-                }
-                if value.len() <= 12 {
-                    byte |= value.len() as u8;
-                } else if value.len() < 269 {
-                    byte |= 13;
-                } else {
-                    byte |= 14;
-                }
-                header.push(byte);
-
-                if delta > 12 && delta < 269 {
-                    header.push((delta - 13) as u8);
-                } else if delta >= 269 {
-                    let fix = delta - 269;
-                    header.push((fix >> 8) as u8);
-                    header.push((fix & 0xFF) as u8);
-                }
-
-                if value.len() > 12 && value.len() < 269 {
-                    header.push((value.len() - 13) as u8);
-                } else if value.len() >= 269 {
-                    let fix = (value.len() - 269) as u16;
-                    header.push((fix >> 8) as u8);
-                    header.push((fix & 0xFF) as u8);
-                }
-
-                options_delta_length += delta;
-
-                options_bytes.reserve(header.len() + value.len());
-                unsafe {
-                    use core::ptr;
-                    let buf_len = options_bytes.len();
-                    ptr::copy(
-                        header.as_ptr(),
-                        options_bytes.as_mut_ptr().add(buf_len),
-                        header.len(),
-                    );
-                    ptr::copy(
-                        value.as_ptr(),
-                        options_bytes.as_mut_ptr().add(buf_len + header.len()),
-                        value.len(),
-                    );
-                    options_bytes
-                        .set_len(buf_len + header.len() + value.len());
-                }
+    /// Returns the block options of the packet, telling which of Block1 and
+    /// Block2 (or both) are present.  Malformed block options are ignored.
+    pub fn any_block(&self) -> Option<BlockKind> {
+        let block1 = self
+            .get_first_option_as::<BlockValue>(CoapOption::Block1)
+            .and_then(|x| x.ok());
+        let block2 = self
+            .get_first_option_as::<BlockValue>(CoapOption::Block2)
+            .and_then(|x| x.ok());
+        match (block1, block2) {
+            (Some(block1), Some(block2)) => {
+                Some(BlockKind::Both { block1, block2 })
             }
+            (Some(block1), None) => Some(BlockKind::Block1(block1)),
+            (None, Some(block2)) => Some(BlockKind::Block2(block2)),
+            (None, None) => None,
         }
+    }
 
-        let mut buf_length = 4 + self.payload.len() + self.token.len();
-        if self.header.code != MessageClass::Empty && !self.payload.is_empty()
-        {
-            buf_length += 1;
-        }
-        buf_length += options_bytes.len();
-
-        if limit.is_some() && buf_length > limit.unwrap() {
-            return Err(MessageError::InvalidPacketLength);
-        }
+    /// Returns the numbers of the critical options (those with an odd number)
+    /// of the packet that aren't known to this crate, i.e. that are
+    /// [`CoapOption::Unknown`].
+    ///
+    /// A server must reject requests carrying such options with 4.02 Bad
+    /// Option (RFC 7252, Section 5.4.1).
+    pub fn unknown_critical_options(&self) -> Vec<u16> {
+        self.options
+            .iter()
+            .filter(|(number, value_list)| {
+                *number & 0x01 == 0x01
+                    && matches!(
+                        CoapOption::from(**number),
+                        CoapOption::Unknown(_)
+                    )
+                    && !value_list.is_empty()
+            })
+            .map(|(number, _)| *number)
+            .collect()
+    }
 
-        let mut buf: Vec<u8> = Vec::with_capacity(buf_length);
-        let header_result = self.header.to_raw().serialize_into(&mut buf);
-
-        match header_result {
-            Ok(_) => {
-                buf.reserve(self.token.len() + options_bytes.len());
-                unsafe {
-                    use core::ptr;
-                    let buf_len = buf.len();
-                    ptr::copy(
-                        self.token.as_ptr(),
-                        buf.as_mut_ptr().add(buf_len),
-                        self.token.len(),
-                    );
-                    ptr::copy(
-                        options_bytes.as_ptr(),
-                        buf.as_mut_ptr().add(buf_len + self.token.len()),
-                        options_bytes.len(),
-                    );
-                    buf.set_len(
-                        buf_len + self.token.len() + options_bytes.len(),
-                    );
-                }
+    /// Splits the options of a request into those that are part of the cache
+    /// key and those that are not (see [`CoapOption::is_no_cache_key`]),
+    /// returned in that order as option number and value pairs.
+    ///
+    /// Both lists are sorted by option number, with the values of a repeated
+    /// option in their original order.
+    #[allow(clippy::type_complexity)]
+    pub fn partition_cache_options(
+        &self,
+    ) -> (Vec<(u16, Vec<u8>)>, Vec<(u16, Vec<u8>)>) {
+        self.options
+            .iter()
+            .flat_map(|(number, value_list)| {
+                value_list.iter().map(move |value| (*number, value.clone()))
+            })
+            .partition(|(number, _)| !CoapOption::is_no_cache_key(*number))
+    }
 
-                if self.header.code != MessageClass::Empty
-                    && !self.payload.is_empty()
-                {
-                    buf.push(0xFF);
-                    buf.reserve(self.payload.len());
-                    unsafe {
-                        use core::ptr;
-                        let buf_len = buf.len();
-                        ptr::copy(
-                            self.payload.as_ptr(),
-                            buf.as_mut_ptr().add(buf.len()),
-                            self.payload.len(),
-                        );
-                        buf.set_len(buf_len + self.payload.len());
-                    }
-                }
-                Ok(buf)
+    /// Rewrites the values of known uint options, such as Max-Age or
+    /// Content-Format, to their minimal-length encoding without leading zero
+    /// bytes, e.g. before caching a leniently parsed packet.
+    pub fn normalize_uint_options(&mut self) {
+        for (number, values) in self.options.iter_mut() {
+            for value in values.iter_mut() {
+                let start =
+                    value.len() - strip_uint_zeros(*number, value).len();
+                value.drain(..start);
             }
-            Err(_) => Err(MessageError::InvalidHeader),
         }
     }
-}
+
+    /// Returns the options of the packet in a canonical encoding, suitable
+    /// for hashing or signing.
+    ///
+    /// The options are emitted in ascending option number order (values of
+    /// a repeated option keep their order), and the values of uint options
+    /// are stripped of leading zero bytes, so that semantically equal packets
+    /// produce identical bytes regardless of how they were built.
+    pub fn canonical_options_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let options = self.options.iter().flat_map(|(number, value_list)| {
+            value_list
+                .iter()
+                .map(move |value| (*number, strip_uint_zeros(*number, value)))
+        });
+        encode_options(&mut bytes, options)
+            .expect("writing to a Vec never fails");
+        bytes
+    }
+
+    /// Returns the encoded packet as a lowercase hex string without
+    /// separators, e.g. for test fixtures that are easy to diff.
+    ///
+    /// Unlike [`Packet::to_bytes`], the message size isn't checked.
+    pub fn to_hex(&self) -> String {
+        let bytes = self
+            .to_bytes_unlimited()
+            .expect("Encoding without a limit can't fail");
+        bytes.iter().fold(
+            String::with_capacity(bytes.len() * 2),
+            |mut hex, byte| {
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            },
+        )
+    }
+
+    /// Decodes a packet from its hex encoding as produced by
+    /// [`Packet::to_hex`].  Digits may be in either case and ASCII whitespace
+    /// is ignored.
+    pub fn from_hex(hex: &str) -> Result<Packet, MessageError> {
+        let digits = hex
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .map(|c| c.to_digit(16).ok_or(MessageError::InvalidHex))
+            .collect::<Result<Vec<_>, _>>()?;
+        if digits.len() % 2 != 0 {
+            return Err(MessageError::InvalidHex);
+        }
+
+        let bytes: Vec<u8> = digits
+            .chunks(2)
+            .map(|pair| (pair[0] << 4 | pair[1]) as u8)
+            .collect();
+        Packet::from_bytes(&bytes)
+    }
+
+    fn to_bytes_internal(
+        &self,
+        limit: Option<usize>,
+    ) -> Result<Vec<u8>, MessageError> {
+        let mut buf: Vec<u8> =
+            Vec::with_capacity(4 + self.token.len() + 1 + self.payload.len());
+        self.to_bytes_writer(&mut buf)?;
+
+        if limit.is_some_and(|limit| buf.len() > limit) {
+            return Err(MessageError::InvalidPacketLength);
+        }
+        Ok(buf)
+    }
+
+    /// Serializes the packet into the given writer, e.g. a fixed-size buffer
+    /// through a [`SliceWriter`] when no allocator is available.
+    ///
+    /// Returns [`MessageError::InvalidTokenLength`] if the token is longer
+    /// than 8 bytes, or the writer's error if it runs out of room, in which
+    /// case it has been left partially written.
+    pub fn to_bytes_writer<W: OptionWriter>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), MessageError> {
+        if self.token.len() > 8 {
+            return Err(MessageError::InvalidTokenLength);
+        }
+        writer.extend_from_slice(&self.header.to_raw().to_bytes())?;
+        writer.extend_from_slice(&self.token)?;
+        encode_options(
+            writer,
+            self.options.iter().flat_map(|(number, value_list)| {
+                value_list.iter().map(move |value| (*number, &value[..]))
+            }),
+        )?;
+
+        if self.header.code != MessageClass::Empty && !self.payload.is_empty()
+        {
+            writer.push(0xFF)?;
+            writer.extend_from_slice(&self.payload)?;
+        }
+        Ok(())
+    }
+}
+
+/// A sink for the bytes of a serialized packet, see
+/// [`Packet::to_bytes_writer`].
+pub trait OptionWriter {
+    /// Appends a single byte, returning an error if there is no room for it.
+    fn push(&mut self, byte: u8) -> Result<(), MessageError>;
+
+    /// Appends all of the bytes, returning an error if there is no room for
+    /// them.
+    fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), MessageError>;
+}
+
+impl OptionWriter for Vec<u8> {
+    fn push(&mut self, byte: u8) -> Result<(), MessageError> {
+        Vec::push(self, byte);
+        Ok(())
+    }
+
+    fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), MessageError> {
+        Vec::extend_from_slice(self, bytes);
+        Ok(())
+    }
+}
+
+/// A writer over a fixed-size buffer, which fails with
+/// [`MessageError::InvalidPacketLength`] once the buffer is full.
+#[derive(Debug)]
+pub struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    /// Creates a writer that starts writing at the beginning of `buf`.
+    pub fn new(buf: &'a mut [u8]) -> SliceWriter<'a> {
+        SliceWriter { buf, len: 0 }
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether nothing has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bytes written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl OptionWriter for SliceWriter<'_> {
+    fn push(&mut self, byte: u8) -> Result<(), MessageError> {
+        self.extend_from_slice(&[byte])
+    }
+
+    fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), MessageError> {
+        let end = self.len + bytes.len();
+        if end > self.buf.len() {
+            return Err(MessageError::InvalidPacketLength);
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Returns the value of option `number` without leading zero bytes if it is
+/// a known uint option, i.e. in its minimal-length encoding, and unchanged
+/// otherwise.
+fn strip_uint_zeros(number: u16, value: &[u8]) -> &[u8] {
+    if !CoapOption::from(number).is_uint() {
+        return value;
+    }
+    let start = value
+        .iter()
+        .position(|&byte| byte != 0)
+        .unwrap_or(value.len());
+    &value[start..]
+}
+
+/// Encodes the given options, which must be sorted by option number, as they
+/// appear in a message.
+fn encode_options<'a, W: OptionWriter>(
+    writer: &mut W,
+    options: impl Iterator<Item = (u16, &'a [u8])>,
+) -> Result<(), MessageError> {
+    let mut options_delta_length = 0;
+    for (number, value) in options {
+        let mut header = [0u8; 1 + 2 + 2];
+        let mut header_len = 1;
+        let delta = number - options_delta_length;
+
+        let mut byte: u8 = 0;
+        if delta <= 12 {
+            byte |= (delta << 4) as u8;
+        } else if delta < 269 {
+            byte |= 13 << 4;
+        } else {
+            byte |= 14 << 4;
+        }
+        if value.len() <= 12 {
+            byte |= value.len() as u8;
+        } else if value.len() < 269 {
+            byte |= 13;
+        } else {
+            byte |= 14;
+        }
+        header[0] = byte;
+
+        if delta > 12 && delta < 269 {
+            header[header_len] = (delta - 13) as u8;
+            header_len += 1;
+        } else if delta >= 269 {
+            let fix = delta - 269;
+            header[header_len] = (fix >> 8) as u8;
+            header[header_len + 1] = (fix & 0xFF) as u8;
+            header_len += 2;
+        }
+
+        if value.len() > 12 && value.len() < 269 {
+            header[header_len] = (value.len() - 13) as u8;
+            header_len += 1;
+        } else if value.len() >= 269 {
+            let fix = (value.len() - 269) as u16;
+            header[header_len] = (fix >> 8) as u8;
+            header[header_len + 1] = (fix & 0xFF) as u8;
+            header_len += 2;
+        }
+
+        options_delta_length += delta;
+
+        writer.extend_from_slice(&header[..header_len])?;
+        writer.extend_from_slice(value)?;
+    }
+    Ok(())
+}
 
 #[cfg(test)]
+#[allow(deprecated)]
 mod test {
     use super::*;
     use crate::{header, option_value::OptionValueString};
@@ -844,6 +1805,60 @@ mod test {
         assert_eq!(packet.payload, "Hello".as_bytes().to_vec());
     }
 
+    #[test]
+    fn test_parse_into() {
+        let buf = [
+            0x44, 0x01, 0x84, 0x9e, 0x51, 0x55, 0x77, 0xe8, 0xb2, 0x48, 0x69,
+            0x04, 0x54, 0x65, 0x73, 0x74, 0x43, 0x61, 0x3d, 0x31, 0xFF, 0x48,
+            0x69,
+        ];
+        let mut options = [OptionRef::default(); 4];
+        let view = Packet::parse_into(&buf, &mut options).unwrap();
+        assert_eq!(view.header.message_id, 33950);
+        assert_eq!(view.token, [0x51, 0x55, 0x77, 0xE8]);
+        assert_eq!(view.option_count, 3);
+        assert_eq!(
+            options[..view.option_count],
+            [
+                OptionRef {
+                    number: CoapOption::UriPath.into(),
+                    value: b"Hi",
+                },
+                OptionRef {
+                    number: CoapOption::UriPath.into(),
+                    value: b"Test",
+                },
+                OptionRef {
+                    number: CoapOption::UriQuery.into(),
+                    value: b"a=1",
+                },
+            ]
+        );
+        assert_eq!(view.payload, b"Hi");
+
+        let mut options = [OptionRef::default(); 2];
+        assert_eq!(
+            Packet::parse_into(&buf, &mut options),
+            Err(MessageError::TooManyOptions)
+        );
+    }
+
+    #[test]
+    fn test_decode_extended_option_delta_and_length() {
+        let mut packet = Packet::new();
+        packet.add_option(CoapOption::Unknown(255), vec![0xAB; 300]);
+        packet.add_option(CoapOption::Unknown(1000), vec![0xCD; 20]);
+        let bytes = packet.to_bytes().unwrap();
+        assert_eq!(Ok(packet), Packet::from_bytes(&bytes));
+
+        // Extended delta overflowing the option number space
+        let buf = [0x40, 0x01, 0x00, 0x00, 0xE0, 0xFF, 0xFF];
+        assert_eq!(
+            Packet::from_bytes(&buf),
+            Err(MessageError::InvalidOptionDelta)
+        );
+    }
+
     #[test]
     fn test_encode_packet_with_options() {
         let mut packet = Packet::new();
@@ -884,6 +1899,33 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_payload_len() {
+        let mut packet = Packet::new();
+        assert_eq!(0, packet.payload_len());
+        assert!(!packet.has_payload());
+
+        packet.payload = b"Hello".to_vec();
+        assert_eq!(5, packet.payload_len());
+        assert!(packet.has_payload());
+    }
+
+    #[test]
+    fn test_take_and_replace_payload() {
+        let mut packet = Packet::new();
+        packet.add_option(CoapOption::UriPath, b"test".to_vec());
+        packet.payload = b"Hello".to_vec();
+        let options = packet.options.clone();
+
+        assert_eq!(b"Hello".to_vec(), packet.take_payload());
+        assert!(!packet.has_payload());
+
+        assert!(packet.replace_payload(b"Hi".to_vec()).is_empty());
+        assert_eq!(b"Hi".to_vec(), packet.replace_payload(b"Yo".to_vec()));
+        assert_eq!(b"Yo".to_vec(), packet.payload);
+        assert_eq!(options, packet.options);
+    }
+
     #[test]
     fn test_encode_decode_content_format() {
         let mut packet = Packet::new();
@@ -927,6 +1969,137 @@ mod test {
         }
     }
 
+    #[test]
+    fn option_number() {
+        assert_eq!(11, CoapOption::UriPath.number());
+        assert_eq!(CoapOption::UriPath, CoapOption::from_number(11));
+        assert_eq!(CoapOption::Unknown(2049), CoapOption::from_number(2049));
+        assert_eq!(2049, CoapOption::Unknown(2049).number());
+    }
+
+    #[test]
+    fn any_block() {
+        let mut p = Packet::new();
+        assert_eq!(p.any_block(), None);
+
+        let block2 = BlockValue::new(3, true, 256).unwrap();
+        p.add_option_as(CoapOption::Block2, block2.clone());
+        assert_eq!(p.any_block(), Some(BlockKind::Block2(block2.clone())));
+
+        let block1 = BlockValue::new(7, false, 64).unwrap();
+        p.add_option_as(CoapOption::Block1, block1.clone());
+        assert_eq!(p.any_block(), Some(BlockKind::Both { block1, block2 }));
+    }
+
+    #[test]
+    fn unknown_critical_options() {
+        let mut p = Packet::new();
+        p.add_option(CoapOption::UriPath, b"a".to_vec());
+        p.add_option(CoapOption::Unknown(65001), vec![0x01]);
+        p.add_option(CoapOption::Unknown(65002), vec![0x02]);
+        assert_eq!(p.unknown_critical_options(), [65001]);
+
+        p.clear_option(CoapOption::Unknown(65001));
+        assert!(p.unknown_critical_options().is_empty());
+    }
+
+    #[test]
+    fn partition_cache_options() {
+        let mut p = Packet::new();
+        p.add_option(CoapOption::UriPath, b"sensors".to_vec());
+        p.add_option(CoapOption::ETag, vec![0x01]);
+        p.add_option(CoapOption::UriPath, b"temp".to_vec());
+        p.add_option(CoapOption::Size2, vec![]);
+        p.add_option(CoapOption::Observe, vec![]);
+        p.add_option(CoapOption::Accept, vec![0x3c]);
+
+        let (cache_key, no_cache_key) = p.partition_cache_options();
+        assert_eq!(
+            cache_key,
+            [
+                (11, b"sensors".to_vec()),
+                (11, b"temp".to_vec()),
+                (17, vec![0x3c]),
+            ]
+        );
+        assert_eq!(no_cache_key, [(4, vec![0x01]), (6, vec![]), (28, vec![])]);
+        assert!(CoapOption::is_no_cache_key(CoapOption::Size1.into()));
+        assert!(!CoapOption::is_no_cache_key(CoapOption::UriQuery.into()));
+    }
+
+    #[test]
+    fn oscore_class() {
+        let class_of =
+            |option: CoapOption| CoapOption::oscore_class(option.into());
+        assert_eq!(OscoreClass::Encrypted, class_of(CoapOption::UriPath));
+        assert_eq!(OscoreClass::Encrypted, class_of(CoapOption::Observe));
+        assert_eq!(
+            OscoreClass::Encrypted,
+            class_of(CoapOption::Unknown(65000))
+        );
+        assert_eq!(OscoreClass::Unprotected, class_of(CoapOption::UriHost));
+        assert_eq!(OscoreClass::Unprotected, class_of(CoapOption::Oscore));
+        assert_eq!(OscoreClass::Unprotected, class_of(CoapOption::ProxyUri));
+    }
+
+    #[test]
+    fn content_format_media_types() {
+        let senml_cbor = ContentFormat::try_from(112).unwrap();
+        assert_eq!(senml_cbor, ContentFormat::ApplicationSenmlCBOR);
+        assert_eq!(senml_cbor.to_string(), "application/senml+cbor");
+        assert_eq!(
+            "application/senml+cbor".parse::<ContentFormat>(),
+            Ok(senml_cbor)
+        );
+        assert_eq!(
+            ContentFormat::TextPlain.to_string(),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(
+            "application/cose; cose-type=\"cose-sign1\"".parse(),
+            Ok(ContentFormat::ApplicationCoseSign1)
+        );
+        assert_eq!(
+            "application/x-unknown".parse::<ContentFormat>(),
+            Err(InvalidContentFormat)
+        );
+
+        assert_eq!(
+            ContentFormat::ApplicationJsonDeflate.to_string(),
+            "application/json"
+        );
+        assert_eq!(
+            ContentFormat::ApplicationJsonDeflate.content_coding(),
+            Some("deflate")
+        );
+        assert_eq!(
+            "application/json".parse(),
+            Ok(ContentFormat::ApplicationJSON)
+        );
+        assert_eq!(
+            "application/json@deflate".parse::<ContentFormat>(),
+            Err(InvalidContentFormat)
+        );
+
+        for i in 0..=u16::MAX {
+            if let Ok(format) = ContentFormat::try_from(usize::from(i)) {
+                assert!(CONTENT_FORMATS.contains(&format));
+                if format.content_coding().is_none() {
+                    assert_eq!(format.to_string().parse(), Ok(format));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn content_format_coap_group() {
+        let format = ContentFormat::try_from(256).unwrap();
+        assert_eq!(format, ContentFormat::ApplicationCoapGroupJson);
+        assert_eq!(usize::from(format), 256);
+        assert_eq!(format.to_string(), "application/coap-group+json");
+        assert_eq!("application/coap-group+json".parse(), Ok(format));
+    }
+
     #[test]
     fn content_format() {
         for i in 0..512 {
@@ -994,6 +2167,32 @@ mod test {
         assert_eq!(actual, Some(expected));
     }
 
+    #[test]
+    fn test_typed_option_getters() {
+        let mut p = Packet::new();
+        assert_eq!(Ok(None), p.get_uint_option(CoapOption::MaxAge));
+        assert_eq!(Ok(None), p.get_string_option(CoapOption::UriHost));
+        assert_eq!(None, p.get_opaque_option(CoapOption::ETag));
+
+        p.add_option(CoapOption::MaxAge, vec![0x01, 0x2C]);
+        p.add_option(CoapOption::UriHost, b"localhost".to_vec());
+        p.add_option(CoapOption::ETag, vec![0xde, 0xad]);
+        assert_eq!(Ok(Some(300)), p.get_uint_option(CoapOption::MaxAge));
+        assert_eq!(
+            Ok(Some("localhost")),
+            p.get_string_option(CoapOption::UriHost)
+        );
+        assert_eq!(
+            Some(&[0xde, 0xad][..]),
+            p.get_opaque_option(CoapOption::ETag)
+        );
+
+        p.add_option(CoapOption::UriQuery, vec![0xfe, 0xff]);
+        assert!(p.get_string_option(CoapOption::UriQuery).is_err());
+        p.add_option(CoapOption::Size1, vec![0; 9]);
+        assert!(p.get_uint_option(CoapOption::Size1).is_err());
+    }
+
     #[test]
     fn observe() {
         let mut p = Packet::new();
@@ -1002,6 +2201,147 @@ mod test {
         assert_eq!(Some(Ok(0)), p.get_observe_value());
     }
 
+    #[test]
+    fn rfc8132_method_codes() {
+        for (method, code) in [
+            (header::RequestType::Fetch, 0x05),
+            (header::RequestType::Patch, 0x06),
+            (header::RequestType::IPatch, 0x07),
+        ] {
+            let mut packet = Packet::new();
+            packet.set_code(MessageClass::Request(method));
+            packet.payload = b"[]".to_vec();
+            let bytes = packet.to_bytes().unwrap();
+            assert_eq!(bytes[1], code);
+            let parsed = Packet::from_bytes(&bytes).unwrap();
+            assert_eq!(parsed.header.code, MessageClass::Request(method));
+        }
+    }
+
+    #[test]
+    fn token_matches() {
+        let request = Packet::new();
+        let mut response = Packet::new();
+        assert!(request.token().is_empty());
+        assert!(response.token_matches(&request));
+
+        response.set_token(vec![0x01, 0x02]);
+        assert_eq!(response.token(), &[0x01, 0x02]);
+        assert!(!response.token_matches(&request));
+
+        let mut other = Packet::new();
+        other.set_token(vec![0x01, 0x03]);
+        assert!(!response.token_matches(&other));
+        other.set_token(vec![0x01, 0x02]);
+        assert!(response.token_matches(&other));
+    }
+
+    #[test]
+    fn set_code() {
+        let mut packet = Packet::new();
+        packet.set_code(MessageClass::Response(header::ResponseType::Created));
+        assert_eq!(packet.header.get_code(), "2.01");
+        packet.set_code(MessageClass::Request(header::RequestType::Get));
+        assert_eq!(packet.to_bytes().unwrap()[1], 0x01);
+    }
+
+    #[test]
+    fn canonical_options_bytes() {
+        let mut a = Packet::new();
+        a.add_option(CoapOption::UriPath, b"sensors".to_vec());
+        a.add_option(CoapOption::UriPath, b"temp".to_vec());
+        a.add_option(CoapOption::ContentFormat, vec![0x00, 0x00]);
+        a.add_option(CoapOption::MaxAge, vec![0x00, 0x00, 0x01, 0x2c]);
+        a.add_option(CoapOption::ETag, vec![0x00, 0x01]);
+
+        let mut b = Packet::new();
+        b.add_option(CoapOption::ETag, vec![0x00, 0x01]);
+        b.add_option_as(CoapOption::MaxAge, OptionValueU32(300));
+        b.add_option(CoapOption::UriPath, b"sensors".to_vec());
+        b.add_option(CoapOption::ContentFormat, vec![]);
+        b.add_option(CoapOption::UriPath, b"temp".to_vec());
+
+        assert_ne!(a.to_bytes().unwrap(), b.to_bytes().unwrap());
+        assert_eq!(a.canonical_options_bytes(), b.canonical_options_bytes());
+        assert_eq!(
+            a.canonical_options_bytes(),
+            [
+                &[0x42, 0x00, 0x01][..],
+                &[0x77][..],
+                b"sensors",
+                &[0x04][..],
+                b"temp",
+                &[0x10][..],
+                &[0x22, 0x01, 0x2c][..],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let mut p = Packet::new();
+        p.set_token(vec![0x01; 4]);
+        p.add_option(CoapOption::UriHost, b"example.com".to_vec());
+        p.add_option(CoapOption::UriPath, b"a".to_vec());
+        p.add_option(CoapOption::UriPath, b"b".to_vec());
+        p.add_option(CoapOption::Unknown(65000), vec![0; 300]);
+        assert_eq!(p.validate(), Ok(()));
+
+        let mut malformed = p.clone();
+        malformed.set_token(vec![0x01; 9]);
+        assert_eq!(
+            malformed.validate(),
+            Err(MessageError::InvalidTokenLength)
+        );
+
+        let mut malformed = p.clone();
+        malformed.add_option(CoapOption::ETag, vec![]);
+        assert_eq!(
+            malformed.validate(),
+            Err(MessageError::InvalidOptionLength)
+        );
+
+        let mut malformed = p.clone();
+        malformed.add_option(CoapOption::ContentFormat, vec![0]);
+        malformed.add_option(CoapOption::ContentFormat, vec![50]);
+        assert_eq!(malformed.validate(), Err(MessageError::RepeatedOption));
+
+        let mut malformed = p.clone();
+        malformed.add_option(CoapOption::ProxyUri, b"coap://h/".to_vec());
+        assert_eq!(
+            malformed.validate(),
+            Err(MessageError::ConflictingOptions)
+        );
+
+        let mut malformed = p;
+        malformed.payload = vec![0; Packet::MAX_SIZE];
+        assert_eq!(
+            malformed.validate(),
+            Err(MessageError::InvalidPacketLength)
+        );
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let mut p = Packet::new();
+        p.header.message_id = 0x1234;
+        p.set_token(vec![0xab, 0xcd]);
+        p.add_option(CoapOption::UriPath, b"a".to_vec());
+        p.payload = b"hi".to_vec();
+
+        let hex = p.to_hex();
+        assert_eq!(hex, "42011234abcdb161ff6869");
+        assert_eq!(Packet::from_hex(&hex), Ok(p.clone()));
+        assert_eq!(Packet::from_hex("42011234 ABCD b161\nff6869"), Ok(p));
+
+        assert_eq!(Packet::from_hex("4201123"), Err(MessageError::InvalidHex));
+        assert_eq!(
+            Packet::from_hex("42011234zz"),
+            Err(MessageError::InvalidHex)
+        );
+    }
+
     #[test]
     fn to_bytes_limits_work() {
         let mut packet = Packet::new();
@@ -1014,4 +2354,265 @@ mod test {
         assert!(packet.to_bytes_with_limit(1380).is_ok());
         assert!(packet.to_bytes_unlimited().is_ok());
     }
+
+    #[test]
+    fn to_bytes_writer_fixed_buffer() {
+        let mut p = Packet::new();
+        p.set_token(vec![0xab, 0xcd]);
+        p.add_option(CoapOption::UriPath, b"sensors".to_vec());
+        p.add_option(CoapOption::UriPath, b"temp".to_vec());
+        p.payload = b"22.5".to_vec();
+        let expected = p.to_bytes().unwrap();
+
+        let mut buf = [0u8; 64];
+        let mut writer = SliceWriter::new(&mut buf);
+        assert!(writer.is_empty());
+        p.to_bytes_writer(&mut writer).unwrap();
+        assert_eq!(writer.written(), &expected[..]);
+
+        let mut buf = [0u8; 16];
+        let mut writer = SliceWriter::new(&mut buf);
+        assert_eq!(
+            p.to_bytes_writer(&mut writer),
+            Err(MessageError::InvalidPacketLength)
+        );
+        assert!(writer.len() <= 16);
+    }
+
+    #[test]
+    fn option_set_deduplicates() {
+        let mut p = Packet::new();
+        p.add_option(CoapOption::IfMatch, vec![0x02]);
+        p.add_option(CoapOption::IfMatch, vec![0x01]);
+        p.add_option(CoapOption::IfMatch, vec![0x02]);
+
+        let set = p.option_set(CoapOption::IfMatch.into());
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&vec![0x01]));
+        assert!(set.contains(&vec![0x02]));
+        assert!(p.option_set(CoapOption::ETag.into()).is_empty());
+    }
+
+    #[test]
+    fn payload_marker_without_payload() {
+        let buf = [0x40, 0x01, 0x00, 0x00, 0xB1, 0x61, 0xFF];
+        assert_eq!(Packet::from_bytes(&buf), Err(MessageError::Malformed));
+        let mut options = [OptionRef::default(); 1];
+        assert_eq!(
+            Packet::parse_into(&buf, &mut options).map(|_| ()),
+            Err(MessageError::Malformed)
+        );
+
+        let mut p = Packet::new();
+        p.add_option(CoapOption::UriPath, b"a".to_vec());
+        let bytes = p.to_bytes().unwrap();
+        assert_eq!(bytes, &buf[..6]);
+        assert_eq!(Packet::from_bytes(&bytes), Ok(p));
+    }
+
+    #[test]
+    fn if_match_round_trip() {
+        let mut p = Packet::new();
+        assert_eq!(p.get_if_match(), None);
+
+        p.set_if_match(IfMatch::Any);
+        let p = Packet::from_bytes(&p.to_bytes().unwrap()).unwrap();
+        assert_eq!(p.get_if_match(), Some(IfMatch::Any));
+
+        let mut p = Packet::new();
+        let etags = vec![vec![0x01, 0x02], vec![0x03]];
+        p.set_if_match(IfMatch::ETags(etags.clone()));
+        let mut p = Packet::from_bytes(&p.to_bytes().unwrap()).unwrap();
+        assert_eq!(p.get_if_match(), Some(IfMatch::ETags(etags)));
+
+        p.add_option(CoapOption::IfMatch, Vec::new());
+        assert_eq!(p.get_if_match(), Some(IfMatch::Any));
+
+        p.set_if_match(IfMatch::ETags(Vec::new()));
+        assert_eq!(p.get_if_match(), None);
+    }
+
+    #[test]
+    fn normalize_uint_options_strips_leading_zeros() {
+        let mut p = Packet::new();
+        p.add_option(CoapOption::MaxAge, vec![0x00, 0x3C]);
+        p.add_option(CoapOption::ContentFormat, vec![0x00]);
+        p.add_option(CoapOption::ETag, vec![0x00, 0x01]);
+
+        p.normalize_uint_options();
+        assert_eq!(p.get_opaque_option(CoapOption::MaxAge), Some(&[0x3C][..]));
+        assert_eq!(
+            p.get_opaque_option(CoapOption::ContentFormat),
+            Some(&[][..])
+        );
+        assert_eq!(
+            p.get_opaque_option(CoapOption::ETag),
+            Some(&[0x00, 0x01][..])
+        );
+    }
+
+    #[test]
+    fn try_set_token_rejects_long_tokens() {
+        let mut p = Packet::new();
+        assert_eq!(p.try_set_token(vec![0x01; 8]), Ok(()));
+        assert_eq!(p.get_token(), &[0x01; 8]);
+
+        assert_eq!(
+            p.try_set_token(vec![0x02; 9]),
+            Err(MessageError::InvalidTokenLength)
+        );
+        assert_eq!(p.get_token(), &[0x01; 8]);
+        assert_eq!(p.header.get_token_length(), 8);
+    }
+
+    #[test]
+    fn set_token_long_tokens_fail_to_serialize() {
+        let mut p = Packet::new();
+        p.set_token(vec![0x01; 8]);
+        let parsed = Packet::from_bytes(&p.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.get_token(), &[0x01; 8]);
+
+        p.set_token(vec![0x02; 9]);
+        assert_eq!(p.to_bytes(), Err(MessageError::InvalidTokenLength));
+        let mut buf = [0u8; 32];
+        assert_eq!(
+            p.to_bytes_writer(&mut SliceWriter::new(&mut buf)),
+            Err(MessageError::InvalidTokenLength)
+        );
+    }
+
+    #[test]
+    fn content_format_compatibility() {
+        assert_eq!(ContentFormat::TextPlain.base_type(), "text/plain");
+        assert_eq!(
+            ContentFormat::ApplicationCBOR.base_type(),
+            "application/cbor"
+        );
+        assert!(ContentFormat::TextPlain
+            .compatible_with(&"text/plain; charset=utf-8".parse().unwrap()));
+        assert!(ContentFormat::ApplicationCoseSign1
+            .compatible_with(&ContentFormat::ApplicationCoseMac0));
+        assert!(!ContentFormat::ApplicationJSON
+            .compatible_with(&ContentFormat::ApplicationCBOR));
+        assert!(!ContentFormat::ApplicationJSON
+            .compatible_with(&ContentFormat::ApplicationJsonDeflate));
+    }
+
+    #[test]
+    fn lazy_packet_finds_options_on_demand() {
+        let mut p = Packet::new();
+        p.try_set_token(vec![0x01, 0x02]).unwrap();
+        p.add_option(CoapOption::UriHost, b"example.com".to_vec());
+        p.add_option(CoapOption::UriPath, b"sensors".to_vec());
+        p.add_option(CoapOption::UriPath, b"temp".to_vec());
+        p.add_option(CoapOption::Unknown(2000), vec![0xAB; 20]);
+        p.payload = b"22.5".to_vec();
+        let bytes = p.to_bytes().unwrap();
+
+        let lazy = LazyPacket::from_bytes(&bytes).unwrap();
+        assert_eq!(lazy.header, p.header);
+        assert_eq!(lazy.token, &[0x01, 0x02]);
+        assert_eq!(lazy.payload, b"22.5");
+        assert_eq!(
+            lazy.find_option(CoapOption::UriPath.into()),
+            Some(&b"sensors"[..])
+        );
+        assert_eq!(lazy.find_option(CoapOption::ETag.into()), None);
+        assert_eq!(lazy.options_iter().count(), 4);
+
+        let options_end = bytes.len() - 1 - lazy.payload.len();
+        assert_eq!(lazy.raw_options(), &bytes[6..options_end]);
+
+        assert_eq!(
+            LazyPacket::from_bytes(&[0x40, 0x01, 0x00, 0x00, 0xF0]),
+            Err(MessageError::InvalidOptionDelta)
+        );
+    }
+
+    #[test]
+    fn apply_template_merges_missing_options() {
+        let mut template = Packet::new();
+        template.add_option(CoapOption::Accept, vec![50]);
+        template.add_option(CoapOption::UriPath, b"default".to_vec());
+
+        let mut p = Packet::new();
+        p.add_option(CoapOption::UriPath, b"sensors".to_vec());
+        p.apply_template(&template);
+
+        assert_eq!(p.get_opaque_option(CoapOption::Accept), Some(&[50][..]));
+        let path: Vec<_> =
+            p.get_option(CoapOption::UriPath).unwrap().iter().collect();
+        assert_eq!(path, [b"sensors"]);
+    }
+
+    #[test]
+    fn request_and_response_codes() {
+        let mut p = Packet::new();
+        assert!(p.is_request());
+        assert!(!p.is_response());
+
+        p.header.set_code_raw(0x45);
+        assert!(p.is_response());
+        p.header.set_code_raw(0x40);
+        assert!(p.is_response());
+        p.header.set_code_raw(0x1F);
+        assert!(p.is_request());
+        p.header.set_code_raw(0x61);
+        assert!(!p.is_request() && !p.is_response());
+
+        p.set_code(MessageClass::Empty);
+        assert!(!p.is_request());
+        assert!(!p.is_response());
+    }
+
+    #[test]
+    fn no_response_round_trip() {
+        let mut p = Packet::new();
+        assert_eq!(p.get_no_response(), None);
+
+        let classes = NoResponseClass::SUCCESS | NoResponseClass::SERVER_ERROR;
+        assert_eq!(classes.bits(), 18);
+        p.set_no_response(classes);
+        assert_eq!(
+            p.get_opaque_option(CoapOption::NoResponse),
+            Some(&[18][..])
+        );
+        let p = Packet::from_bytes(&p.to_bytes().unwrap()).unwrap();
+        let decoded = p.get_no_response().unwrap();
+        assert_eq!(decoded, classes);
+        assert!(decoded.contains(NoResponseClass::SUCCESS));
+        assert!(!decoded.contains(NoResponseClass::CLIENT_ERROR));
+
+        let mut p = Packet::new();
+        p.set_no_response(NoResponseClass::NONE);
+        assert_eq!(p.get_opaque_option(CoapOption::NoResponse), Some(&[][..]));
+        assert_eq!(p.get_no_response(), Some(NoResponseClass::NONE));
+
+        assert_eq!(NoResponseClass::from_bits(1), None);
+    }
+
+    #[test]
+    fn from_bytes_shorter_than_header() {
+        let buf = [0x40, 0x01, 0x00, 0x00];
+        for len in 0..4 {
+            assert_eq!(
+                Packet::from_bytes(&buf[..len]),
+                Err(MessageError::UnexpectedEnd)
+            );
+        }
+        assert!(Packet::from_bytes(&buf).is_ok());
+    }
+
+    #[test]
+    fn compute_etag_from_payload() {
+        let mut p = Packet::new();
+        p.payload = b"a".to_vec();
+        let etag = p.compute_etag_from_payload();
+        assert_eq!(etag, [0xaf, 0x63, 0xdc, 0x4c, 0x86, 0x01, 0xec, 0x8c]);
+        assert_eq!(p.clone().compute_etag_from_payload(), etag);
+
+        p.payload = b"b".to_vec();
+        assert_ne!(p.compute_etag_from_payload(), etag);
+        assert_eq!(p.compute_etag_from_payload().len(), 8);
+    }
 }
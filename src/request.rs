@@ -4,15 +4,29 @@ use alloc::{
 };
 use core::convert::TryFrom;
 
+#[cfg(feature = "std")]
+use crate::header::MessageType;
 use crate::{
-    error::{HandlingError, IncompatibleOptionValueFormat, InvalidObserve},
+    error::{
+        HandlingError, IncompatibleOptionValueFormat, InvalidBlockValue,
+        InvalidObserve, InvalidUri, ObserveNotAllowed,
+    },
     header::{MessageClass, RequestType as Method},
-    option_value::OptionValueString,
+    option_value::{
+        BlockValue, OptionValueString, OptionValueU16, OptionValueU32,
+        MAX_SIZE_EXPONENT,
+    },
     packet::{CoapOption, ObserveOption, Packet},
     response::CoapResponse,
     ContentFormat,
 };
 
+/// Default port of the `coap` scheme.
+const COAP_DEFAULT_PORT: u16 = 5683;
+
+/// Default port of the `coaps` scheme.
+const COAPS_DEFAULT_PORT: u16 = 5684;
+
 /// The CoAP request.
 #[derive(Clone, Debug, PartialEq)]
 pub struct CoapRequest<Endpoint> {
@@ -39,6 +53,90 @@ impl<Endpoint> CoapRequest<Endpoint> {
         }
     }
 
+    /// Creates a confirmable GET request for a `coap` or `coaps` URI, with a
+    /// randomly generated token.
+    #[cfg(feature = "std")]
+    pub fn get(uri: &str) -> Result<CoapRequest<Endpoint>, InvalidUri> {
+        Self::with_uri(Method::Get, uri, Vec::new())
+    }
+
+    /// Creates a confirmable POST request for a `coap` or `coaps` URI, with a
+    /// randomly generated token and the given (possibly empty) payload.
+    #[cfg(feature = "std")]
+    pub fn post(
+        uri: &str,
+        payload: Vec<u8>,
+    ) -> Result<CoapRequest<Endpoint>, InvalidUri> {
+        Self::with_uri(Method::Post, uri, payload)
+    }
+
+    /// Creates a confirmable PUT request for a `coap` or `coaps` URI, with a
+    /// randomly generated token and the given (possibly empty) payload.
+    #[cfg(feature = "std")]
+    pub fn put(
+        uri: &str,
+        payload: Vec<u8>,
+    ) -> Result<CoapRequest<Endpoint>, InvalidUri> {
+        Self::with_uri(Method::Put, uri, payload)
+    }
+
+    /// Creates a confirmable DELETE request for a `coap` or `coaps` URI, with
+    /// a randomly generated token.
+    #[cfg(feature = "std")]
+    pub fn delete(uri: &str) -> Result<CoapRequest<Endpoint>, InvalidUri> {
+        Self::with_uri(Method::Delete, uri, Vec::new())
+    }
+
+    /// Creates a confirmable FETCH request (RFC 8132) for a `coap` or `coaps`
+    /// URI, with a randomly generated token and the given payload describing
+    /// the parts of the resource to fetch.
+    #[cfg(feature = "std")]
+    pub fn fetch(
+        uri: &str,
+        payload: Vec<u8>,
+    ) -> Result<CoapRequest<Endpoint>, InvalidUri> {
+        Self::with_uri(Method::Fetch, uri, payload)
+    }
+
+    /// Creates a confirmable PATCH request (RFC 8132) for a `coap` or `coaps`
+    /// URI, with a randomly generated token and the given patch document.
+    #[cfg(feature = "std")]
+    pub fn patch(
+        uri: &str,
+        payload: Vec<u8>,
+    ) -> Result<CoapRequest<Endpoint>, InvalidUri> {
+        Self::with_uri(Method::Patch, uri, payload)
+    }
+
+    /// Creates a confirmable iPATCH request (RFC 8132) for a `coap` or
+    /// `coaps` URI, with a randomly generated token and the given patch
+    /// document.
+    #[cfg(feature = "std")]
+    pub fn ipatch(
+        uri: &str,
+        payload: Vec<u8>,
+    ) -> Result<CoapRequest<Endpoint>, InvalidUri> {
+        Self::with_uri(Method::IPatch, uri, payload)
+    }
+
+    #[cfg(feature = "std")]
+    fn with_uri(
+        method: Method,
+        uri: &str,
+        payload: Vec<u8>,
+    ) -> Result<CoapRequest<Endpoint>, InvalidUri> {
+        let mut request = CoapRequest::new();
+        request.set_uri(uri)?;
+        request.set_method(method);
+        request.message.header.set_type(MessageType::Confirmable);
+        request
+            .message
+            .try_set_token(random_token())
+            .expect("random tokens are 8 bytes long");
+        request.message.payload = payload;
+        Ok(request)
+    }
+
     /// Applies the given error to the request and returns true if that was
     /// successful.
     pub fn apply_from_error(&mut self, error: HandlingError) -> bool {
@@ -88,6 +186,103 @@ impl<Endpoint> CoapRequest<Endpoint> {
         }
     }
 
+    /// Sets the Uri-Host, Uri-Port, Uri-Path and Uri-Query options from a
+    /// `coap` or `coaps` URI (RFC 7252, Section 6.4), replacing any previous
+    /// ones.
+    ///
+    /// Uri-Host is omitted if the host is an IP literal, as is Uri-Port if
+    /// the port is the default port of the scheme.
+    pub fn set_uri(&mut self, uri: &str) -> Result<(), InvalidUri> {
+        let (scheme, rest) =
+            uri.split_once("://").ok_or(InvalidUri::UnsupportedScheme)?;
+        let default_port = if scheme.eq_ignore_ascii_case("coap") {
+            COAP_DEFAULT_PORT
+        } else if scheme.eq_ignore_ascii_case("coaps") {
+            COAPS_DEFAULT_PORT
+        } else {
+            return Err(InvalidUri::UnsupportedScheme);
+        };
+        if rest.contains('#') {
+            return Err(InvalidUri::Fragment);
+        }
+
+        let (authority, path_and_query) = match rest.find(['/', '?']) {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        let (path, query) = match path_and_query.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (path_and_query, ""),
+        };
+
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(literal) => {
+                let (_, port) =
+                    literal.split_once(']').ok_or(InvalidUri::InvalidHost)?;
+                (None, port)
+            }
+            None => {
+                let (host, port) = match authority.find(':') {
+                    Some(i) => authority.split_at(i),
+                    None => (authority, ""),
+                };
+                if host.is_empty() || host.contains('@') {
+                    return Err(InvalidUri::InvalidHost);
+                }
+                let host = percent_decode(host)?.to_ascii_lowercase();
+                (Some(host).filter(|host| !is_ipv4_literal(host)), port)
+            }
+        };
+        let port = match port.strip_prefix(':') {
+            Some("") => default_port,
+            Some(port) if port.bytes().all(|b| b.is_ascii_digit()) => {
+                port.parse().map_err(|_| InvalidUri::InvalidPort)?
+            }
+            Some(_) => return Err(InvalidUri::InvalidPort),
+            None if port.is_empty() => default_port,
+            None => return Err(InvalidUri::InvalidHost),
+        };
+
+        let segments = match path.strip_prefix('/') {
+            Some(segments) if !segments.is_empty() => segments
+                .split('/')
+                .map(percent_decode)
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => Vec::new(),
+        };
+        let arguments = match query {
+            "" => Vec::new(),
+            query => query
+                .split('&')
+                .map(percent_decode)
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+
+        for option in [
+            CoapOption::UriHost,
+            CoapOption::UriPort,
+            CoapOption::UriPath,
+            CoapOption::UriQuery,
+        ] {
+            self.message.options.remove(&option.into());
+        }
+        if let Some(host) = host {
+            self.message.add_option(CoapOption::UriHost, host);
+        }
+        if port != default_port {
+            self.message
+                .add_option_as(CoapOption::UriPort, OptionValueU16(port));
+        }
+        for segment in segments {
+            self.message.add_option(CoapOption::UriPath, segment);
+        }
+        for argument in arguments {
+            self.message.add_option(CoapOption::UriQuery, argument);
+        }
+
+        Ok(())
+    }
+
     /// Returns the path.
     pub fn get_path(&self) -> String {
         match self.message.get_option(CoapOption::UriPath) {
@@ -104,6 +299,33 @@ impl<Endpoint> CoapRequest<Endpoint> {
         }
     }
 
+    /// Returns the path as a string of the Uri-Path segments that always
+    /// starts with a single `/`, which is all it consists of if there are
+    /// none.  This makes it suitable for routing, unlike
+    /// [`CoapRequest::get_path`].
+    ///
+    /// Segments that aren't valid UTF-8 are converted lossily.
+    pub fn normalized_path(&self) -> String {
+        let mut path = String::new();
+        if let Some(segments) = self.message.get_option(CoapOption::UriPath) {
+            for segment in segments {
+                path.push('/');
+                path.push_str(&String::from_utf8_lossy(segment));
+            }
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        path
+    }
+
+    /// Returns the method and the [normalized
+    /// path](CoapRequest::normalized_path) of the request, to look up its
+    /// handler in a dispatch table.
+    pub fn route_key(&self) -> (Method, String) {
+        (*self.get_method(), self.normalized_path())
+    }
+
     /// Returns the path as a vector (as it is encoded in CoAP rather than in
     /// HTTP-style paths).
     pub fn get_path_as_vec(
@@ -143,6 +365,103 @@ impl<Endpoint> CoapRequest<Endpoint> {
         let value = u32::try_from(usize::from(flag)).unwrap();
         self.message.set_observe_value(value);
     }
+
+    /// Sets the flag in the Observe option like
+    /// [`CoapRequest::set_observe_flag`], failing unless the request is a GET
+    /// or FETCH, the only methods that can be observed (RFC 7641 and RFC
+    /// 8132).  The method must therefore be set beforehand.
+    pub fn try_set_observe_flag(
+        &mut self,
+        flag: ObserveOption,
+    ) -> Result<(), ObserveNotAllowed> {
+        let method = *self.get_method();
+        if !matches!(method, Method::Get | Method::Fetch) {
+            return Err(ObserveNotAllowed { method });
+        }
+
+        self.set_observe_flag(flag);
+        Ok(())
+    }
+
+    /// Asks the server for block `num` of the resource, with blocks of
+    /// 2^(`size_exponent` + 4) bytes, by adding a Block2 option to the
+    /// request (RFC 7959, Section 2.4).
+    ///
+    /// Returns an error if the size exponent is above 6 (1024 bytes) or the
+    /// block number doesn't fit into the option.
+    pub fn request_block2(
+        &mut self,
+        num: u32,
+        size_exponent: u8,
+    ) -> Result<(), InvalidBlockValue> {
+        if size_exponent > MAX_SIZE_EXPONENT {
+            return Err(InvalidBlockValue::SizeExponentOutOfRange(
+                size_exponent,
+            ));
+        }
+        let num = usize::try_from(num)
+            .map_err(InvalidBlockValue::TypeBoundsError)?;
+        let block = BlockValue::new(num, false, 1 << (size_exponent + 4))?;
+
+        self.message.clear_option(CoapOption::Block2);
+        self.message.add_option_as(CoapOption::Block2, block);
+        Ok(())
+    }
+
+    /// Asks the server to indicate the total size of the resource in its
+    /// response by adding a Size2 option with value 0 (RFC 7959, Section 4).
+    pub fn request_size2(&mut self) {
+        self.message.clear_option(CoapOption::Size2);
+        self.message
+            .add_option_as(CoapOption::Size2, OptionValueU32(0));
+    }
+
+    /// Returns true if the payload of the request has no Content-Format
+    /// option or one of the `supported` formats. Otherwise the request should
+    /// be answered with [`CoapResponse::unsupported_content_format`].
+    pub fn content_format_supported(
+        &self,
+        supported: &[ContentFormat],
+    ) -> bool {
+        match self
+            .message
+            .get_first_option_as::<OptionValueU16>(CoapOption::ContentFormat)
+        {
+            Some(Ok(value)) => supported
+                .iter()
+                .any(|&format| usize::from(format) == usize::from(value.0)),
+            Some(Err(_)) => false,
+            None => true,
+        }
+    }
+
+    /// Returns true if the client asked for the total size of the resource
+    /// with a Size2 option of value 0.
+    pub fn wants_size_hint(&self) -> bool {
+        matches!(
+            self.message
+                .get_first_option_as::<OptionValueU32>(CoapOption::Size2),
+            Some(Ok(OptionValueU32(0)))
+        )
+    }
+}
+
+impl<Endpoint: Clone> CoapRequest<Endpoint> {
+    /// Returns a copy of the request to retransmit under a new message ID,
+    /// keeping the token and options.
+    pub fn clone_for_retry(
+        &self,
+        new_message_id: u16,
+    ) -> CoapRequest<Endpoint> {
+        let mut request = self.clone();
+        request.message.header.message_id = new_message_id;
+        // The prepared response must still acknowledge the message it
+        // belongs to.
+        if let Some(response) = &mut request.response {
+            response.message.header.message_id = new_message_id;
+        }
+        request
+    }
 }
 
 impl<Endpoint> Default for CoapRequest<Endpoint> {
@@ -155,11 +474,77 @@ impl<Endpoint> Default for CoapRequest<Endpoint> {
     }
 }
 
+impl<Endpoint> From<CoapRequest<Endpoint>> for Packet {
+    fn from(request: CoapRequest<Endpoint>) -> Packet {
+        request.message
+    }
+}
+
+impl<Endpoint> AsRef<Packet> for CoapRequest<Endpoint> {
+    fn as_ref(&self) -> &Packet {
+        &self.message
+    }
+}
+
+/// Decodes the percent-encoded octets of a URI component.
+fn percent_decode(component: &str) -> Result<Vec<u8>, InvalidUri> {
+    let mut decoded = Vec::with_capacity(component.len());
+    let mut bytes = component.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+
+        let digits = match (bytes.next(), bytes.next()) {
+            (Some(high), Some(low)) => char::from(high)
+                .to_digit(16)
+                .zip(char::from(low).to_digit(16)),
+            _ => None,
+        };
+        let (high, low) = digits.ok_or(InvalidUri::InvalidPercentEncoding)?;
+        decoded.push((high << 4 | low) as u8);
+    }
+    Ok(decoded)
+}
+
+/// Returns true if `host` is an IPv4 address in dotted-decimal notation.
+fn is_ipv4_literal(host: &[u8]) -> bool {
+    let octets: Vec<&[u8]> = host.split(|&b| b == b'.').collect();
+    octets.len() == 4
+        && octets.iter().all(|octet| {
+            !octet.is_empty()
+                && octet.len() <= 3
+                && octet.iter().all(u8::is_ascii_digit)
+                && octet
+                    .iter()
+                    .fold(0u16, |acc, &b| acc * 10 + u16::from(b - b'0'))
+                    <= 255
+        })
+}
+
+/// Returns a random token of the maximum length, as recommended for requests
+/// that may be exposed to off-path attackers (RFC 7252, Section 5.3.1).
+#[cfg(feature = "std")]
+fn random_token() -> Vec<u8> {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    RandomState::new()
+        .build_hasher()
+        .finish()
+        .to_be_bytes()
+        .to_vec()
+}
+
 #[cfg(test)]
+#[allow(deprecated)]
 mod test {
     use super::*;
     use crate::header::MessageType;
 
+    #[allow(dead_code)]
+    #[derive(Clone)]
     struct Endpoint(String);
 
     #[test]
@@ -269,6 +654,87 @@ mod test {
         assert_eq!(path3, request.get_path());
     }
 
+    #[test]
+    fn test_normalized_path() {
+        let mut request: CoapRequest<Endpoint> = CoapRequest::new();
+        assert_eq!("/", request.normalized_path());
+
+        request.set_path("a/b");
+        assert_eq!("/a/b", request.normalized_path());
+        request.set_path("/a/b");
+        assert_eq!("/a/b", request.normalized_path());
+        request.set_path("/a/");
+        assert_eq!("/a/", request.normalized_path());
+    }
+
+    #[test]
+    fn test_request_block2() {
+        let mut request: CoapRequest<Endpoint> = CoapRequest::new();
+        request.request_block2(2, 6).unwrap();
+        let block = request
+            .message
+            .get_first_option_as::<BlockValue>(CoapOption::Block2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            block,
+            BlockValue {
+                num: 2,
+                more: false,
+                size_exponent: 6
+            }
+        );
+        assert!(request.message.payload.is_empty());
+
+        request.request_block2(3, 6).unwrap();
+        assert_eq!(
+            request
+                .message
+                .get_option(CoapOption::Block2)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            request.request_block2(0, 7),
+            Err(InvalidBlockValue::SizeExponentOutOfRange(7))
+        );
+    }
+
+    #[test]
+    fn test_packet_conversions() {
+        let mut request: CoapRequest<Endpoint> = CoapRequest::new();
+        request.set_method(Method::Put);
+        request.set_path("/a/b");
+        request.message.payload = b"on".to_vec();
+        assert_eq!(request.as_ref().payload, b"on");
+
+        let bytes = Packet::from(request).to_bytes().unwrap();
+        let packet = Packet::from_bytes(&bytes).unwrap();
+        let request =
+            CoapRequest::from_packet(packet, Endpoint("peer".to_string()));
+        assert_eq!(Method::Put, *request.get_method());
+        assert_eq!("/a/b", request.normalized_path());
+
+        let response = request.response.unwrap();
+        assert_eq!(response.as_ref().header.message_id, 0);
+        let packet: Packet = response.into();
+        assert_eq!(packet.header.get_type(), MessageType::Acknowledgement);
+    }
+
+    #[test]
+    fn test_route_key() {
+        let mut request: CoapRequest<Endpoint> = CoapRequest::new();
+        request.set_method(Method::Get);
+        request
+            .message
+            .add_option(CoapOption::UriPath, b"a".to_vec());
+        request
+            .message
+            .add_option(CoapOption::UriPath, b"b".to_vec());
+        assert_eq!((Method::Get, "/a/b".to_string()), request.route_key());
+    }
+
     #[test]
     fn test_path_as_vec() {
         let mut request: CoapRequest<Endpoint> = CoapRequest::new();
@@ -295,6 +761,96 @@ mod test {
             .expect_err("must be a utf-8 decoding error");
     }
 
+    #[test]
+    fn test_clone_for_retry() {
+        let mut packet = Packet::new();
+        packet.header.message_id = 42;
+        packet.set_token(vec![0x17, 0x38]);
+        packet.add_option(CoapOption::UriPath, b"test-interface".to_vec());
+        let endpoint = Endpoint(String::from("127.0.0.1:1234"));
+        let request = CoapRequest::from_packet(packet, endpoint);
+
+        let retry = request.clone_for_retry(43);
+        assert_eq!(43, retry.message.header.message_id);
+        assert_eq!(43, retry.response.unwrap().message.header.message_id);
+        assert_eq!(request.message.get_token(), retry.message.get_token());
+        assert_eq!(
+            request.message.get_option(CoapOption::UriPath),
+            retry.message.get_option(CoapOption::UriPath)
+        );
+        assert_eq!(42, request.message.header.message_id);
+    }
+
+    #[test]
+    fn test_request_size2() {
+        let mut request: CoapRequest<Endpoint> = CoapRequest::new();
+        assert!(!request.wants_size_hint());
+
+        request.request_size2();
+        let bytes = request.message.to_bytes().unwrap();
+        // Option delta 13 with extended delta 15 and zero length for the
+        // empty uint value.
+        assert_eq!(bytes[4..], [0xD0, 0x0F]);
+
+        let packet = Packet::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            Some(&Vec::new()),
+            packet.get_first_option(CoapOption::Size2)
+        );
+        let endpoint = Endpoint(String::from("127.0.0.1:1234"));
+        let received = CoapRequest::from_packet(packet, endpoint);
+        assert!(received.wants_size_hint());
+    }
+
+    #[test]
+    fn test_content_format_supported() {
+        let supported = [ContentFormat::ApplicationJSON];
+        let mut request: CoapRequest<Endpoint> = CoapRequest::new();
+        request.set_method(Method::Put);
+        request.message.try_set_token(vec![0x42]).unwrap();
+        assert!(request.content_format_supported(&supported));
+
+        request
+            .message
+            .set_content_format(ContentFormat::ApplicationJSON);
+        assert!(request.content_format_supported(&supported));
+
+        request.message.clear_option(CoapOption::ContentFormat);
+        request
+            .message
+            .set_content_format(ContentFormat::ApplicationCBOR);
+        assert!(!request.content_format_supported(&supported));
+
+        let response =
+            CoapResponse::unsupported_content_format(&request.message)
+                .unwrap();
+        assert_eq!("4.15", response.message.header.get_code());
+        assert_eq!(response.message.get_token(), &[0x42]);
+    }
+
+    #[test]
+    fn test_observe_flag_requires_get_or_fetch() {
+        let mut request: CoapRequest<Endpoint> = CoapRequest::new();
+        request.set_method(Method::Post);
+        assert_eq!(
+            request.try_set_observe_flag(ObserveOption::Register),
+            Err(ObserveNotAllowed {
+                method: Method::Post
+            })
+        );
+        assert!(request.get_observe_flag().is_none());
+
+        request.set_method(Method::Fetch);
+        assert_eq!(
+            request.try_set_observe_flag(ObserveOption::Register),
+            Ok(())
+        );
+        assert_eq!(
+            request.get_observe_flag(),
+            Some(Ok(ObserveOption::Register))
+        );
+    }
+
     #[test]
     fn test_unknown_observe_flag() {
         let mut request: CoapRequest<Endpoint> = CoapRequest::new();
@@ -316,4 +872,109 @@ mod test {
         let actual = request.get_observe_flag();
         assert_eq!(actual, expected);
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_get_uri() {
+        let request: CoapRequest<Endpoint> =
+            CoapRequest::get("coap://h/a/b?x=1").unwrap();
+        assert_eq!(&Method::Get, request.get_method());
+        assert_eq!(
+            MessageType::Confirmable,
+            request.message.header.get_type()
+        );
+        assert_eq!(8, request.message.get_token().len());
+        assert_eq!(
+            Some(&b"h".to_vec()),
+            request.message.get_first_option(CoapOption::UriHost)
+        );
+        assert!(request.message.get_option(CoapOption::UriPort).is_none());
+        assert_eq!(vec!["a", "b"], request.get_path_as_vec().unwrap());
+        assert_eq!(
+            Some(&b"x=1".to_vec()),
+            request.message.get_first_option(CoapOption::UriQuery)
+        );
+
+        let other: CoapRequest<Endpoint> =
+            CoapRequest::get("coap://h/a/b?x=1").unwrap();
+        assert_ne!(request.message.get_token(), other.message.get_token());
+
+        let request: CoapRequest<Endpoint> =
+            CoapRequest::put("coaps://[::1]:61616/%7Euser/", b"on".to_vec())
+                .unwrap();
+        assert_eq!(&Method::Put, request.get_method());
+        assert_eq!(b"on", &request.message.payload[..]);
+        assert!(request.message.get_option(CoapOption::UriHost).is_none());
+        assert_eq!(
+            Some(Ok(OptionValueU16(61616))),
+            request
+                .message
+                .get_first_option_as::<OptionValueU16>(CoapOption::UriPort)
+        );
+        assert_eq!(vec!["~user", ""], request.get_path_as_vec().unwrap());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_rfc8132_methods() {
+        let uri = "coap://h/r";
+        for (request, method) in [
+            (CoapRequest::fetch(uri, b"{}".to_vec()), Method::Fetch),
+            (CoapRequest::patch(uri, b"{}".to_vec()), Method::Patch),
+            (CoapRequest::ipatch(uri, b"{}".to_vec()), Method::IPatch),
+        ] {
+            let request: CoapRequest<Endpoint> = request.unwrap();
+            let bytes = request.message.to_bytes().unwrap();
+            let received = CoapRequest::from_packet(
+                Packet::from_bytes(&bytes).unwrap(),
+                Endpoint(String::from("127.0.0.1:1234")),
+            );
+            assert_eq!(&method, received.get_method());
+            assert_eq!(b"{}", &received.message.payload[..]);
+        }
+    }
+
+    #[test]
+    fn test_set_uri() {
+        let mut request: CoapRequest<Endpoint> = CoapRequest::new();
+        request.set_path("/old/path");
+        request.set_uri("COAPS://Example.COM:5684").unwrap();
+        assert_eq!(
+            Some(&b"example.com".to_vec()),
+            request.message.get_first_option(CoapOption::UriHost)
+        );
+        assert!(request.message.get_option(CoapOption::UriPort).is_none());
+        assert!(request.message.get_option(CoapOption::UriPath).is_none());
+
+        request.set_uri("coap://192.0.2.1:5684?a&b%3D").unwrap();
+        assert!(request.message.get_option(CoapOption::UriHost).is_none());
+        assert_eq!(
+            Some(Ok(OptionValueU16(5684))),
+            request
+                .message
+                .get_first_option_as::<OptionValueU16>(CoapOption::UriPort)
+        );
+        assert_eq!(
+            Some(&[b"a".to_vec(), b"b=".to_vec()].into()),
+            request.message.get_option(CoapOption::UriQuery)
+        );
+
+        assert_eq!(
+            Err(InvalidUri::UnsupportedScheme),
+            request.set_uri("http://example.com/")
+        );
+        assert_eq!(
+            Err(InvalidUri::Fragment),
+            request.set_uri("coap://example.com/#top")
+        );
+        assert_eq!(Err(InvalidUri::InvalidHost), request.set_uri("coap:///a"));
+        assert_eq!(
+            Err(InvalidUri::InvalidPort),
+            request.set_uri("coap://example.com:99999")
+        );
+        assert_eq!(
+            Err(InvalidUri::InvalidPercentEncoding),
+            request.set_uri("coap://example.com/%4")
+        );
+    }
 }
@@ -1,8 +1,19 @@
+use core::{str, time::Duration};
+
 use crate::{
-    header::{MessageClass, MessageType, ResponseType as Status},
-    packet::Packet,
+    error::{InvalidObserve, ResponseCodeMismatch},
+    header::{MessageClass, MessageType, RequestType, ResponseType as Status},
+    link_format::{ErrorLinkFormat, LinkFormatParser},
+    option_value::{BlockValue, OptionValueU32},
+    packet::{CoapOption, ContentFormat, Packet},
 };
 
+/// Max-Age of responses without a Max-Age option (RFC 7252, Section 5.10.5).
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// The largest sequence number the Observe option can carry (2^24 - 1).
+const MAX_OBSERVE_SEQUENCE: u32 = 0xFF_FFFF;
+
 /// The CoAP response.
 #[derive(Clone, Debug, PartialEq)]
 pub struct CoapResponse {
@@ -23,11 +34,280 @@ impl CoapResponse {
         packet.header.set_type(response_type);
         packet.header.code = MessageClass::Response(Status::Content);
         packet.header.message_id = request.header.message_id;
+        #[allow(deprecated)]
         packet.set_token(request.get_token().to_vec());
 
         Some(CoapResponse { message: packet })
     }
 
+    /// Wraps a received packet as a response, or returns `None` if its code
+    /// isn't a response code.  In particular, empty messages (0.00), such as
+    /// separate acknowledgements, aren't responses.
+    pub fn from_packet(packet: Packet) -> Option<CoapResponse> {
+        if !packet.is_response() {
+            return None;
+        }
+        Some(CoapResponse { message: packet })
+    }
+
+    /// Creates a 2.31 Continue response asking the client to send the next
+    /// block of a Block1 transfer, acknowledging the received block by
+    /// echoing `echoed_block`.
+    pub fn continue_block1(
+        request: &Packet,
+        echoed_block: BlockValue,
+    ) -> Option<CoapResponse> {
+        let mut response = CoapResponse::new(request)?;
+        response.set_status(Status::Continue);
+        response
+            .message
+            .add_option_as(CoapOption::Block1, echoed_block);
+        Some(response)
+    }
+
+    /// Creates a 4.13 Request Entity Too Large response, indicating the
+    /// largest request body the server accepts in a Size1 option so that the
+    /// client can retry with a smaller one (RFC 7959, Section 4).
+    pub fn entity_too_large(
+        request: &Packet,
+        max_size: u32,
+    ) -> Option<CoapResponse> {
+        let mut response = CoapResponse::new(request)?;
+        response.set_status(Status::RequestEntityTooLarge);
+        response
+            .message
+            .add_option_as(CoapOption::Size1, OptionValueU32(max_size));
+        Some(response)
+    }
+
+    /// Creates a 4.15 Unsupported Content-Format response, for requests whose
+    /// payload is in a format the server can't handle (RFC 7252, Section
+    /// 5.9.2.10).
+    pub fn unsupported_content_format(
+        request: &Packet,
+    ) -> Option<CoapResponse> {
+        let mut response = CoapResponse::new(request)?;
+        response.set_status(Status::UnsupportedContentFormat);
+        Some(response)
+    }
+
+    /// Creates a 5.03 Service Unavailable response, asking the client to
+    /// retry after `retry_after` with a Max-Age option (RFC 7252, Section
+    /// 5.9.3.4).  The delay is rounded up to whole seconds.
+    pub fn service_unavailable(
+        request: &Packet,
+        retry_after: Duration,
+    ) -> Option<CoapResponse> {
+        let mut response = CoapResponse::new(request)?;
+        response.set_status(Status::ServiceUnavailable);
+        let mut seconds = retry_after.as_secs();
+        if retry_after.subsec_nanos() > 0 {
+            seconds += 1;
+        }
+        response.message.add_option_as(
+            CoapOption::MaxAge,
+            OptionValueU32(u32::try_from(seconds).unwrap_or(u32::MAX)),
+        );
+        Some(response)
+    }
+
+    /// Creates a 2.03 Valid response, confirming that the representation
+    /// identified by the request's ETag is still current.
+    pub fn valid(request: &Packet) -> Option<CoapResponse> {
+        let mut response = CoapResponse::new(request)?;
+        response.set_status(Status::Valid);
+        Some(response)
+    }
+
+    /// Creates an error response to a confirmable request, piggybacked on its
+    /// acknowledgement so that no separate empty ACK is needed, with an
+    /// optional diagnostic payload (see [`CoapResponse::with_diagnostic`]).
+    ///
+    /// Returns `None` if the request isn't confirmable.
+    pub fn piggybacked_error(
+        request: &Packet,
+        status: Status,
+        diagnostic: Option<&str>,
+    ) -> Option<CoapResponse> {
+        if request.header.get_type() != MessageType::Confirmable {
+            return None;
+        }
+
+        let mut response = CoapResponse::new(request)?;
+        response.set_status(status);
+        if let Some(message) = diagnostic {
+            response.with_diagnostic(message);
+        }
+        Some(response)
+    }
+
+    /// Sets a human-readable diagnostic message as the payload, typically
+    /// used to explain an error response, along with the Content-Format
+    /// `text/plain;charset=utf-8` (0).
+    pub fn with_diagnostic(&mut self, message: &str) {
+        self.message.payload = message.as_bytes().to_vec();
+        self.message.clear_option(CoapOption::ContentFormat);
+        self.message.set_content_format(ContentFormat::TextPlain);
+    }
+
+    /// Returns a parser over the links in the payload if the response's
+    /// Content-Format is `application/link-format` (40), as is the case for
+    /// `/.well-known/core`.
+    ///
+    /// Returns `None` for any other or a missing Content-Format, and an error
+    /// if the payload isn't a valid link-format document.
+    pub fn as_link_format(
+        &self,
+    ) -> Option<Result<LinkFormatParser<'_>, ErrorLinkFormat>> {
+        if self.message.get_content_format()
+            != Some(ContentFormat::ApplicationLinkFormat)
+        {
+            return None;
+        }
+
+        let parser = match str::from_utf8(&self.message.payload) {
+            Ok(links) => LinkFormatParser::new(links),
+            Err(_) => return Some(Err(ErrorLinkFormat::ParseError)),
+        };
+        match parser.clone().find_map(Result::err) {
+            Some(err) => Some(Err(err)),
+            None => Some(Ok(parser)),
+        }
+    }
+
+    /// Copies the options with the given numbers from `request` into the
+    /// response, replacing any values the response already has for them.
+    /// Options that `request` doesn't carry are left untouched.
+    ///
+    /// This lets intermediaries decide explicitly which options, e.g.
+    /// elective ones they don't know, are carried over to a response.
+    pub fn copy_options_from(&mut self, request: &Packet, numbers: &[u16]) {
+        for &number in numbers {
+            if let Some(values) = request.get_option(CoapOption::from(number))
+            {
+                if !values.is_empty() {
+                    self.message
+                        .set_option(CoapOption::from(number), values.clone());
+                }
+            }
+        }
+    }
+
+    /// Turns the response into an observe notification with the given
+    /// sequence number, which must fit in the 24 bits of the Observe option.
+    ///
+    /// Piggybacked responses become non-confirmable, since notifications
+    /// sent after the registration can't be carried in an acknowledgement;
+    /// confirmable and non-confirmable responses keep their type.  The
+    /// message ID is left as it is, so callers must give each notification a
+    /// new one before sending it.
+    pub fn into_notification(
+        mut self,
+        seq: u32,
+    ) -> Result<CoapResponse, InvalidObserve> {
+        if seq > MAX_OBSERVE_SEQUENCE {
+            return Err(InvalidObserve);
+        }
+
+        if self.message.header.get_type() == MessageType::Acknowledgement {
+            self.message.header.set_type(MessageType::NonConfirmable);
+        }
+        self.message.set_observe_value(seq);
+        Ok(self)
+    }
+
+    /// Returns how long to wait before retrying the request if this is a 5.03
+    /// Service Unavailable response, as indicated by its Max-Age option (60
+    /// seconds if absent).
+    ///
+    /// Returns `None` for other responses or a malformed Max-Age.
+    pub fn retry_after(&self) -> Option<Duration> {
+        if self.message.header.code
+            != MessageClass::Response(Status::ServiceUnavailable)
+        {
+            return None;
+        }
+
+        match self
+            .message
+            .get_first_option_as::<OptionValueU32>(CoapOption::MaxAge)
+        {
+            Some(Ok(max_age)) => Some(Duration::from_secs(max_age.0.into())),
+            Some(Err(_)) => None,
+            None => Some(DEFAULT_MAX_AGE),
+        }
+    }
+
+    /// Returns true if a stored copy of the response is still fresh after
+    /// `age` has elapsed since it was received, according to its Max-Age
+    /// option (60 seconds if absent).
+    ///
+    /// The caller measures `age`, ideally with a monotonic clock.  Responses
+    /// with a malformed Max-Age are never fresh.
+    pub fn is_fresh(&self, age: Duration) -> bool {
+        let max_age = match self
+            .message
+            .get_first_option_as::<OptionValueU32>(CoapOption::MaxAge)
+        {
+            Some(Ok(max_age)) => Duration::from_secs(u64::from(max_age.0)),
+            Some(Err(_)) => return false,
+            None => DEFAULT_MAX_AGE,
+        };
+        age < max_age
+    }
+
+    /// Checks that the response code is one that is expected in response to
+    /// a request with the given method (RFC 7252, Section 5.8 and RFC 8132),
+    /// which helps to catch handler bugs.
+    ///
+    /// Error codes are accepted for any method, as is any response to an
+    /// unknown method.
+    pub fn validate_for_method(
+        &self,
+        method: RequestType,
+    ) -> Result<(), ResponseCodeMismatch> {
+        if method == RequestType::UnKnown {
+            return Ok(());
+        }
+
+        let status = match self.message.header.code {
+            MessageClass::Response(status) => status,
+            _ => Status::UnKnown,
+        };
+        let is_expected = match status {
+            Status::Created | Status::Changed => matches!(
+                method,
+                RequestType::Post
+                    | RequestType::Put
+                    | RequestType::Patch
+                    | RequestType::IPatch
+            ),
+            Status::Deleted => {
+                matches!(method, RequestType::Delete | RequestType::Post)
+            }
+            Status::Valid | Status::Content => {
+                matches!(method, RequestType::Get | RequestType::Fetch)
+            }
+            // Only requests with a body are uploaded with Block1.
+            Status::Continue => matches!(
+                method,
+                RequestType::Post
+                    | RequestType::Put
+                    | RequestType::Fetch
+                    | RequestType::Patch
+                    | RequestType::IPatch
+            ),
+            Status::UnKnown => false,
+            _ => status.is_error(),
+        };
+
+        if is_expected {
+            Ok(())
+        } else {
+            Err(ResponseCodeMismatch { method, status })
+        }
+    }
+
     /// Sets the status.
     pub fn set_status(&mut self, status: Status) {
         self.message.header.code = MessageClass::Response(status);
@@ -41,6 +321,7 @@ impl CoapResponse {
             MessageClass::Response(Status::Valid) => &Status::Valid,
             MessageClass::Response(Status::Changed) => &Status::Changed,
             MessageClass::Response(Status::Content) => &Status::Content,
+            MessageClass::Response(Status::Continue) => &Status::Continue,
 
             MessageClass::Response(Status::BadRequest) => &Status::BadRequest,
             MessageClass::Response(Status::Unauthorized) => {
@@ -86,9 +367,24 @@ impl CoapResponse {
     }
 }
 
+impl From<CoapResponse> for Packet {
+    fn from(response: CoapResponse) -> Packet {
+        response.message
+    }
+}
+
+impl AsRef<Packet> for CoapResponse {
+    fn as_ref(&self) -> &Packet {
+        &self.message
+    }
+}
+
 #[cfg(test)]
+#[allow(deprecated)]
 mod test {
     use super::*;
+    use crate::option_value::OptionValueU16;
+    use alloc::vec::Vec;
 
     #[test]
     fn test_new_response_valid() {
@@ -103,10 +399,293 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_continue_block1() {
+        let mut request = Packet::new();
+        request.header.set_type(MessageType::Confirmable);
+        request.header.message_id = 7;
+        let block1 = BlockValue::new(2, true, 64).unwrap();
+        request.add_option_as(CoapOption::Block1, block1.clone());
+
+        let response =
+            CoapResponse::continue_block1(&request, block1.clone()).unwrap();
+        assert_eq!(&Status::Continue, response.get_status());
+        assert_eq!("2.31", response.message.header.get_code());
+        assert_eq!(7, response.message.header.message_id);
+        assert_eq!(
+            Some(Ok(block1)),
+            response
+                .message
+                .get_first_option_as::<BlockValue>(CoapOption::Block1)
+        );
+    }
+
+    #[test]
+    fn test_entity_too_large() {
+        let response =
+            CoapResponse::entity_too_large(&Packet::new(), 65536).unwrap();
+        assert_eq!(&Status::RequestEntityTooLarge, response.get_status());
+        assert_eq!("4.13", response.message.header.get_code());
+        assert_eq!(
+            Some(Ok(OptionValueU32(65536))),
+            response
+                .message
+                .get_first_option_as::<OptionValueU32>(CoapOption::Size1)
+        );
+    }
+
+    #[test]
+    fn test_service_unavailable() {
+        let response = CoapResponse::service_unavailable(
+            &Packet::new(),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!("5.03", response.message.header.get_code());
+        assert_eq!(Some(Duration::from_secs(5)), response.retry_after());
+
+        let response = CoapResponse::service_unavailable(
+            &Packet::new(),
+            Duration::from_millis(1500),
+        )
+        .unwrap();
+        assert_eq!(Some(Duration::from_secs(2)), response.retry_after());
+
+        let mut response = CoapResponse::new(&Packet::new()).unwrap();
+        assert_eq!(None, response.retry_after());
+        response.set_status(Status::ServiceUnavailable);
+        assert_eq!(Some(Duration::from_secs(60)), response.retry_after());
+    }
+
+    #[test]
+    fn test_valid() {
+        let packet = Packet::new();
+        let response = CoapResponse::valid(&packet).unwrap();
+        assert_eq!(&Status::Valid, response.get_status());
+        assert_eq!("2.03", response.message.header.get_code());
+    }
+
+    #[test]
+    fn test_piggybacked_error() {
+        let mut request = Packet::new();
+        request.header.set_type(MessageType::Confirmable);
+        request.header.message_id = 0x1234;
+        request.set_token(vec![0xab]);
+
+        let response = CoapResponse::piggybacked_error(
+            &request,
+            Status::BadRequest,
+            Some("missing query"),
+        )
+        .unwrap();
+        assert_eq!(
+            MessageType::Acknowledgement,
+            response.message.header.get_type()
+        );
+        assert_eq!("4.00", response.message.header.get_code());
+        assert_eq!(0x1234, response.message.header.message_id);
+        assert_eq!(&[0xab], response.message.get_token());
+        assert_eq!(b"missing query", &response.message.payload[..]);
+        assert_eq!(
+            Some(ContentFormat::TextPlain),
+            response.message.get_content_format()
+        );
+
+        let response =
+            CoapResponse::piggybacked_error(&request, Status::NotFound, None)
+                .unwrap();
+        assert!(response.message.payload.is_empty());
+        assert!(response.message.get_content_format().is_none());
+
+        request.header.set_type(MessageType::NonConfirmable);
+        assert!(CoapResponse::piggybacked_error(
+            &request,
+            Status::BadRequest,
+            None
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_validate_for_method() {
+        let mut response = CoapResponse::new(&Packet::new()).unwrap();
+        assert_eq!(
+            Err(ResponseCodeMismatch {
+                method: RequestType::Delete,
+                status: Status::Content,
+            }),
+            response.validate_for_method(RequestType::Delete)
+        );
+        assert!(response.validate_for_method(RequestType::Get).is_ok());
+        assert!(response.validate_for_method(RequestType::Fetch).is_ok());
+        assert!(response.validate_for_method(RequestType::UnKnown).is_ok());
+
+        response.set_status(Status::Created);
+        assert!(response.validate_for_method(RequestType::Post).is_ok());
+        assert!(response.validate_for_method(RequestType::Get).is_err());
+
+        response.set_status(Status::Deleted);
+        assert!(response.validate_for_method(RequestType::Delete).is_ok());
+        assert!(response.validate_for_method(RequestType::Put).is_err());
+
+        response.set_status(Status::Continue);
+        assert!(response.validate_for_method(RequestType::Put).is_ok());
+        assert!(response.validate_for_method(RequestType::Get).is_err());
+        assert!(response.validate_for_method(RequestType::Delete).is_err());
+
+        response.set_status(Status::NotFound);
+        assert!(response.validate_for_method(RequestType::Delete).is_ok());
+
+        response.message.header.code = MessageClass::Empty;
+        assert!(response.validate_for_method(RequestType::Get).is_err());
+    }
+
+    #[test]
+    fn test_copy_options_from() {
+        let mut request = Packet::new();
+        request.add_option(CoapOption::Unknown(65004), vec![0x01]);
+        request.add_option(CoapOption::Unknown(65004), vec![0x02]);
+        request.add_option(CoapOption::Unknown(65008), vec![0x03]);
+        request.add_option(CoapOption::UriPath, b"a".to_vec());
+
+        let mut response = CoapResponse::new(&request).unwrap();
+        response
+            .message
+            .add_option(CoapOption::Unknown(65004), vec![0xff]);
+        response.copy_options_from(&request, &[65004, 65012]);
+
+        assert_eq!(
+            Some(&[vec![0x01], vec![0x02]].into()),
+            response.message.get_option(CoapOption::Unknown(65004))
+        );
+        assert!(response
+            .message
+            .get_option(CoapOption::Unknown(65008))
+            .is_none());
+        assert!(response
+            .message
+            .get_option(CoapOption::Unknown(65012))
+            .is_none());
+        assert!(response.message.get_option(CoapOption::UriPath).is_none());
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        let mut response = CoapResponse::new(&Packet::new()).unwrap();
+        assert!(response.is_fresh(Duration::from_secs(59)));
+        assert!(!response.is_fresh(Duration::from_secs(60)));
+
+        response
+            .message
+            .add_option_as(CoapOption::MaxAge, OptionValueU32(30));
+        assert!(response.is_fresh(Duration::from_secs(10)));
+        assert!(!response.is_fresh(Duration::from_secs(40)));
+
+        response.message.clear_option(CoapOption::MaxAge);
+        response.message.add_option(CoapOption::MaxAge, vec![1; 5]);
+        assert!(!response.is_fresh(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_with_diagnostic() {
+        let mut response = CoapResponse::new(&Packet::new()).unwrap();
+        response
+            .message
+            .set_content_format(ContentFormat::ApplicationJSON);
+        response.set_status(Status::NotFound);
+        response.with_diagnostic("resource not found");
+
+        assert_eq!("4.04", response.message.header.get_code());
+        assert_eq!(b"resource not found", &response.message.payload[..]);
+        assert_eq!(
+            Some(Ok(OptionValueU16(0))),
+            response.message.get_first_option_as::<OptionValueU16>(
+                CoapOption::ContentFormat
+            )
+        );
+        assert_eq!(
+            1,
+            response
+                .message
+                .get_option(CoapOption::ContentFormat)
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_as_link_format() {
+        let mut response = CoapResponse::new(&Packet::new()).unwrap();
+        response.message.payload =
+            b"</sensors/temp>;rt=\"temperature\",</led>".to_vec();
+        assert!(response.as_link_format().is_none());
+
+        response
+            .message
+            .set_content_format(ContentFormat::ApplicationLinkFormat);
+        let links: Vec<_> = response
+            .as_link_format()
+            .unwrap()
+            .unwrap()
+            .map(|link| link.unwrap().0)
+            .collect();
+        assert_eq!(links, ["/sensors/temp", "/led"]);
+
+        response.message.payload = b"sensors/temp".to_vec();
+        assert_eq!(
+            response.as_link_format(),
+            Some(Err(ErrorLinkFormat::ParseError))
+        );
+
+        response.message.clear_option(CoapOption::ContentFormat);
+        response
+            .message
+            .set_content_format(ContentFormat::TextPlain);
+        assert!(response.as_link_format().is_none());
+    }
+
+    #[test]
+    fn test_from_packet() {
+        let mut packet = Packet::new();
+        packet.set_code(MessageClass::Empty);
+        packet.header.set_type(MessageType::Acknowledgement);
+        assert!(CoapResponse::from_packet(packet.clone()).is_none());
+
+        packet.set_code(MessageClass::Request(RequestType::Get));
+        assert!(CoapResponse::from_packet(packet.clone()).is_none());
+
+        packet.set_code(MessageClass::Response(Status::Content));
+        let response = CoapResponse::from_packet(packet).unwrap();
+        assert_eq!(&Status::Content, response.get_status());
+    }
+
     #[test]
     fn test_new_response_invalid() {
         let mut packet = Packet::new();
         packet.header.set_type(MessageType::Acknowledgement);
         assert!(CoapResponse::new(&packet).is_none());
     }
+
+    #[test]
+    fn test_into_notification() {
+        let mut request = Packet::new();
+        request.header.set_type(MessageType::Confirmable);
+        let mut response = CoapResponse::new(&request).unwrap();
+        response.set_status(Status::Content);
+        response.message.payload = b"22.5".to_vec();
+
+        let notification = response.clone().into_notification(1).unwrap();
+        assert_eq!(notification.message.get_observe_value(), Some(Ok(1)));
+        assert_eq!(
+            notification.message.header.get_type(),
+            MessageType::NonConfirmable
+        );
+        assert_eq!(*notification.get_status(), Status::Content);
+
+        assert!(response.clone().into_notification(0xFF_FFFF).is_ok());
+        assert_eq!(
+            response.into_notification(0x100_0000).map(|_| ()),
+            Err(InvalidObserve)
+        );
+    }
 }